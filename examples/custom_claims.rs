@@ -1,4 +1,4 @@
-use medallion::{Header, Payload, Token};
+use medallion::{Header, Payload, Token, UnverifiedToken};
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug)]
@@ -32,23 +32,27 @@ fn new_token(user_id: &str, password: &str) -> Option<String> {
     };
     let token = Token::new(header, payload);
 
-    token.sign(b"secret_key").ok()
+    token.sign(b"an example secret at least 32 bytes long").ok()
 }
 
 fn login(token: &str) -> Option<String> {
-    let token = Token::<(), Custom>::parse(token).unwrap();
-
-    if token.verify(b"secret_key").unwrap() {
-        Some(token.payload.claims.unwrap().user_id)
-    } else {
-        None
-    }
+    let token = UnverifiedToken::<(), Custom>::parse(token).ok()?;
+
+    // the claims can only be read without an insecure_ prefix once the token is verified
+    let token = token
+        .verify(b"an example secret at least 32 bytes long")
+        .ok()?;
+    token
+        .into_inner()
+        .payload
+        .claims
+        .map(|claims| claims.user_id)
 }
 
 fn main() {
     let token = new_token("Random User", "password").unwrap();
 
-    let logged_in_user = login(&*token).unwrap();
+    let logged_in_user = login(&token).unwrap();
 
     assert_eq!(logged_in_user, "Random User");
 }
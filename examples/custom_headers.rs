@@ -1,4 +1,4 @@
-use medallion::{DefaultPayload, DefaultToken, Header};
+use medallion::{DefaultPayload, DefaultToken, Header, UnverifiedToken};
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Serialize, Deserialize, PartialEq, Debug)]
@@ -34,23 +34,22 @@ fn new_token(sub: &str, password: &str) -> Option<String> {
     };
     let token = DefaultToken::new(header, payload);
 
-    token.sign(b"secret_key").ok()
+    token.sign(b"an example secret at least 32 bytes long").ok()
 }
 
 fn login(token: &str) -> Option<String> {
-    let token = DefaultToken::<Custom>::parse(token).unwrap();
+    let token = UnverifiedToken::<Custom, ()>::parse(token).ok()?;
 
-    if token.verify(b"secret_key").unwrap() {
-        Some(token.payload.sub.unwrap())
-    } else {
-        None
-    }
+    let token = token
+        .verify(b"an example secret at least 32 bytes long")
+        .ok()?;
+    token.into_inner().payload.sub
 }
 
 fn main() {
     let token = new_token("Random User", "password").unwrap();
 
-    let logged_in_user = login(&*token).unwrap();
+    let logged_in_user = login(&token).unwrap();
 
     assert_eq!(logged_in_user, "Random User");
 }
@@ -1,6 +1,7 @@
 extern crate medallion;
 
-use medallion::{DefaultPayload, DefaultToken, Header};
+use chrono::Duration;
+use medallion::{DefaultPayload, DefaultToken, Header, UnverifiedToken};
 
 fn new_token(user_id: &str, password: &str) -> Option<String> {
     // dummy auth, in a real application using something like openidconnect, this would be some
@@ -13,31 +14,32 @@ fn new_token(user_id: &str, password: &str) -> Option<String> {
 
     // can satisfy Header's generic parameter with an empty type
     let header: Header = Header::default();
-    let payload = DefaultPayload {
-        iss: Some("example.com".into()),
-        sub: Some(user_id.into()),
-        ..DefaultPayload::default()
-    };
+    let payload = DefaultPayload::builder()
+        .issuer("example.com")
+        .subject(user_id)
+        .issued_now()
+        .expires_in(Duration::hours(1))
+        .build()
+        .ok()?;
     let token = DefaultToken::new(header, payload);
 
-    token.sign(b"secret_key").ok()
+    token.sign(b"an example secret at least 32 bytes long").ok()
 }
 
 fn login(token: &str) -> Option<String> {
-    let token: DefaultToken<()> = DefaultToken::parse(token).unwrap();
+    let token: UnverifiedToken = UnverifiedToken::parse(token).ok()?;
 
     // the key for HMAC is some secret known to trusted/trusting parties
-    if token.verify(b"secret_key").unwrap() {
-        token.payload.sub
-    } else {
-        None
-    }
+    let token = token
+        .verify(b"an example secret at least 32 bytes long")
+        .ok()?;
+    token.into_inner().payload.sub
 }
 
 fn main() {
     let token = new_token("Random User", "password").unwrap();
 
-    let logged_in_user = login(&*token).unwrap();
+    let logged_in_user = login(&token).unwrap();
 
     assert_eq!(logged_in_user, "Random User");
 }
@@ -1,7 +1,7 @@
 extern crate medallion;
 extern crate openssl;
 
-use medallion::{Algorithm, DefaultPayload, DefaultToken, Header};
+use medallion::{Algorithm, DefaultPayload, DefaultToken, Header, UnverifiedToken};
 use openssl::rsa;
 
 fn new_token(private_key: &[u8], user_id: &str, password: &str) -> Option<String> {
@@ -29,13 +29,10 @@ fn new_token(private_key: &[u8], user_id: &str, password: &str) -> Option<String
 }
 
 fn login(public_key: &[u8], token: &str) -> Option<String> {
-    let token: DefaultToken<()> = DefaultToken::parse(token).unwrap();
+    let token: UnverifiedToken = UnverifiedToken::parse(token).ok()?;
 
-    if token.verify(public_key).unwrap() {
-        token.payload.sub
-    } else {
-        None
-    }
+    let token = token.verify(public_key).ok()?;
+    token.into_inner().payload.sub
 }
 
 fn main() {
@@ -46,9 +43,10 @@ fn main() {
         &keypair.private_key_to_pem().unwrap(),
         "Random User",
         "password",
-    ).unwrap();
+    )
+    .unwrap();
 
-    let logged_in_user = login(&keypair.public_key_to_pem().unwrap(), &*token).unwrap();
+    let logged_in_user = login(&keypair.public_key_to_pem().unwrap(), &token).unwrap();
 
     assert_eq!(logged_in_user, "Random User");
 }
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Duration, Utc};
+use std::{fmt::Debug, sync::Mutex};
+
+/// The source of the current time for validating `nbf` and `exp` and for stamping `iat`, so that
+/// tests and simulations need not depend on the wall clock.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system's wall clock, the default everywhere a clock is used.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that stands still at a given time until it is moved, for testing time based rules
+/// deterministically.
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> FixedClock {
+        FixedClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.lock() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.lock() += by;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DateTime<Utc>> {
+        // a panic elsewhere while holding the lock cannot leave a time half written
+        self.now
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, FixedClock, SystemClock};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn fixed_clock() {
+        let start = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+        let clock = FixedClock::new(start);
+        assert_eq!(start, clock.now());
+        assert_eq!(start, clock.now());
+
+        clock.advance(Duration::seconds(90));
+        assert_eq!(start + Duration::seconds(90), clock.now());
+
+        clock.set(start);
+        assert_eq!(start, clock.now());
+    }
+
+    #[test]
+    fn system_clock() {
+        let before = Utc::now();
+        let now = SystemClock.now();
+        assert!(before <= now && now <= Utc::now());
+    }
+}
@@ -0,0 +1,446 @@
+use super::Result;
+use crate::{error::Error, header::Algorithm, jwk::Jwk, secret::SecretKey};
+use anyhow::format_err;
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use zeroize::Zeroizing;
+
+#[cfg(not(any(feature = "openssl", feature = "rust-crypto")))]
+compile_error!("medallion requires either the `openssl` or the `rust-crypto` feature");
+
+#[cfg(feature = "openssl")]
+mod openssl;
+// only the cross backend tests reach the pure Rust backend when OpenSSL is also enabled
+#[cfg(feature = "rust-crypto")]
+#[cfg_attr(feature = "openssl", allow(dead_code))]
+mod rust_crypto;
+
+#[cfg(feature = "openssl")]
+pub use self::openssl::OpenSsl;
+#[cfg(feature = "rust-crypto")]
+#[cfg_attr(feature = "openssl", allow(unused_imports))]
+pub use self::rust_crypto::RustCrypto;
+
+/// The backend used by the crate, OpenSSL whenever it is enabled, falling back to the pure Rust
+/// implementations otherwise.
+#[cfg(feature = "openssl")]
+pub type DefaultBackend = OpenSsl;
+#[cfg(all(not(feature = "openssl"), feature = "rust-crypto"))]
+pub type DefaultBackend = RustCrypto;
+
+/// The primitive operations each cryptographic backend must provide, working on raw bytes so that
+/// base64 encoding stays in one place. Signatures for the elliptic curve algorithms are always the
+/// fixed width, concatenated `r || s` form that JWS requires.
+pub trait Backend {
+    fn sign(data: &[u8], key: &[u8], algorithm: &Algorithm) -> Result<Vec<u8>>;
+
+    fn verify(signature: &[u8], data: &[u8], key: &[u8], algorithm: &Algorithm) -> Result<bool>;
+
+    /// Decrypts a password protected private key into unencrypted PKCS#8 DER.
+    fn decrypt_private_key(key: &[u8], password: &[u8]) -> Result<SecretKey>;
+
+    fn sha256(data: &[u8]) -> Result<Vec<u8>>;
+
+    /// The size in bits of the modulus of an RSA private or public key.
+    fn rsa_bits(key: &[u8]) -> Result<usize>;
+
+    /// Fills the buffer from a cryptographically secure random number generator.
+    fn random(buf: &mut [u8]) -> Result<()>;
+
+    /// Generates a new key pair for one of the asymmetric algorithms, returning the private key as
+    /// unencrypted PKCS#8 DER and the public key as SubjectPublicKeyInfo DER.
+    fn generate(algorithm: &Algorithm) -> Result<(SecretKey, Vec<u8>)>;
+
+    /// Describes a private or public key for one of the asymmetric algorithms as a JWK, only
+    /// including the private members when asked to, which requires a private key.
+    fn jwk(key: &[u8], algorithm: &Algorithm, private: bool) -> Result<Jwk>;
+}
+
+pub fn sign(data: &str, key: &[u8], algorithm: &Algorithm) -> Result<String> {
+    let sig = DefaultBackend::sign(data.as_bytes(), key, algorithm)?;
+    Ok(encode_config(&sig, URL_SAFE_NO_PAD))
+}
+
+pub fn verify(target: &str, data: &str, key: &[u8], algorithm: &Algorithm) -> Result<bool> {
+    let target_bytes: Vec<u8> = decode_config(target, URL_SAFE_NO_PAD)?;
+    DefaultBackend::verify(&target_bytes, data.as_bytes(), key, algorithm)
+}
+
+/// Checks a key against the RFC 7518 minimums, an HMAC secret at least as long as the hash output
+/// and an RSA modulus of at least 2048 bits. Curve keys have fixed sizes and always pass.
+pub fn check_key_strength(key: &[u8], algorithm: &Algorithm) -> Result<()> {
+    let (bits, minimum) = match (hmac_len(algorithm), *algorithm) {
+        (Some(len), _) => (key.len() * 8, len * 8),
+        (None, Algorithm::RS256) | (None, Algorithm::RS384) | (None, Algorithm::RS512) => {
+            (DefaultBackend::rsa_bits(key)?, 2048)
+        }
+        _ => return Ok(()),
+    };
+    if bits < minimum {
+        Err(Error::WeakKey {
+            algorithm: *algorithm,
+            bits,
+            minimum,
+        }
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+    DefaultBackend::sha256(data)
+}
+
+pub fn random(buf: &mut [u8]) -> Result<()> {
+    DefaultBackend::random(buf)
+}
+
+/// A random 128 bit identifier, such as a `jti`, as unpadded base64url.
+pub fn random_id() -> Result<String> {
+    let mut id = [0; 16];
+    random(&mut id)?;
+    Ok(b64(&id))
+}
+
+/// Generates a key pair for any algorithm, HMAC getting only a random secret and no public half.
+pub fn generate(algorithm: &Algorithm) -> Result<(SecretKey, Option<Vec<u8>>)> {
+    match hmac_len(algorithm) {
+        Some(len) => {
+            let mut secret = Zeroizing::new(vec![0; len]);
+            random(&mut secret)?;
+            Ok((SecretKey::from(&secret[..]), None))
+        }
+        None => {
+            let (private, public) = DefaultBackend::generate(algorithm)?;
+            Ok((private, Some(public)))
+        }
+    }
+}
+
+/// Describes a key as a JWK, an HMAC secret being an `oct` key that has no public form.
+pub fn jwk(key: &[u8], algorithm: &Algorithm, private: bool) -> Result<Jwk> {
+    let jwk = match hmac_len(algorithm) {
+        Some(_) if !private => return Err(format_err!("An HMAC secret has no public form.")),
+        Some(_) => Jwk {
+            kty: "oct".into(),
+            k: Some(b64_secret(key)),
+            ..Jwk::default()
+        },
+        None => DefaultBackend::jwk(key, algorithm, private)?,
+    };
+    Ok(Jwk {
+        alg: Some(*algorithm),
+        ..jwk
+    })
+}
+
+/// The length of the hash output for the HMAC algorithms, or `None` for the asymmetric ones.
+pub fn hmac_len(algorithm: &Algorithm) -> Option<usize> {
+    match *algorithm {
+        Algorithm::HS256 => Some(32),
+        Algorithm::HS384 => Some(48),
+        Algorithm::HS512 => Some(64),
+        _ => None,
+    }
+}
+
+/// The JWK name of the curve for the elliptic curve algorithms.
+pub fn curve_name(algorithm: &Algorithm) -> Option<&'static str> {
+    match *algorithm {
+        Algorithm::ES256 => Some("P-256"),
+        Algorithm::ES384 => Some("P-384"),
+        Algorithm::ES512 => Some("P-521"),
+        Algorithm::EdDSA => Some("Ed25519"),
+        _ => None,
+    }
+}
+
+pub fn b64(bytes: &[u8]) -> String {
+    encode_config(bytes, URL_SAFE_NO_PAD)
+}
+
+/// Encodes private key material as `b64` does, zeroing the encoding once it is dropped.
+pub fn b64_secret(bytes: &[u8]) -> Zeroizing<String> {
+    Zeroizing::new(b64(bytes))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{sign, verify};
+    use crate::header::Algorithm;
+
+    #[test]
+    pub fn sign_data_hmac() {
+        let header = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9";
+        let claims = "eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiYWRtaW4iOnRydWV9";
+        let real_sig = "TJVA95OrM7E2cBab30RMHrHDcEfxjoYZgeFONFh7HgQ";
+        let data = format!("{}.{}", header, claims);
+
+        let sig = sign(&data, b"secret", &Algorithm::HS256);
+
+        assert_eq!(sig.unwrap(), real_sig);
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    pub fn sign_and_verify_data_rsa() {
+        let header = "eyJhbGciOiJSUzI1NiIsInR5cCI6IkpXVCJ9";
+        let claims = "eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiYWRtaW4iOnRydWV9";
+
+        let data = format!("{}.{}", header, claims);
+
+        let keypair = openssl::rsa::Rsa::generate(2048).unwrap();
+
+        let sig = sign(
+            &data,
+            &keypair.private_key_to_pem().unwrap(),
+            &Algorithm::RS256,
+        )
+        .unwrap();
+
+        assert!(verify(
+            &sig,
+            &data,
+            &keypair.public_key_to_pem().unwrap(),
+            &Algorithm::RS256
+        )
+        .unwrap());
+    }
+
+    #[test]
+    pub fn verify_data_hmac() {
+        let header = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9";
+        let claims = "eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiYWRtaW4iOnRydWV9";
+        let target = "TJVA95OrM7E2cBab30RMHrHDcEfxjoYZgeFONFh7HgQ";
+        let data = format!("{}.{}", header, claims);
+
+        assert!(verify(target, &data, b"secret", &Algorithm::HS256).unwrap());
+    }
+
+    /// Each encoding of a key is generated with OpenSSL, then checked against every backend.
+    #[cfg(feature = "openssl")]
+    mod key_formats {
+        #[cfg(feature = "rust-crypto")]
+        use super::super::RustCrypto;
+        use super::super::{Backend, OpenSsl};
+        use crate::{header::Algorithm, x5c::tests::issue};
+        use openssl::{
+            ec::{EcGroup, EcKey},
+            nid::Nid,
+            pkey::PKey,
+            symm::Cipher,
+        };
+
+        const DATA: &[u8] = b"eyJhbGciOiJSUzI1NiJ9.eyJzdWIiOiIxMjM0NTY3ODkwIn0";
+
+        fn check_all<B: Backend>(algorithm: Algorithm, privates: &[Vec<u8>], publics: &[Vec<u8>]) {
+            for private in privates {
+                let sig = B::sign(DATA, private, &algorithm).unwrap();
+                for public in publics {
+                    assert!(B::verify(&sig, DATA, public, &algorithm).unwrap());
+                }
+            }
+        }
+
+        fn rsa<B: Backend>() {
+            let issued = issue("Signer", None, false);
+            let rsa = issued.key.rsa().unwrap();
+            let privates = vec![
+                rsa.private_key_to_pem().unwrap(),
+                rsa.private_key_to_der().unwrap(),
+                issued.key.private_key_to_pem_pkcs8().unwrap(),
+                issued.key.private_key_to_pkcs8().unwrap(),
+            ];
+            let publics = vec![
+                issued.key.public_key_to_pem().unwrap(),
+                issued.key.public_key_to_der().unwrap(),
+                rsa.public_key_to_pem_pkcs1().unwrap(),
+                rsa.public_key_to_der_pkcs1().unwrap(),
+                issued.cert.to_pem().unwrap(),
+                issued.cert.to_der().unwrap(),
+            ];
+            check_all::<B>(Algorithm::RS256, &privates, &publics);
+        }
+
+        fn ec<B: Backend>() {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            let ec = EcKey::generate(&group).unwrap();
+            let pkey = PKey::from_ec_key(ec.clone()).unwrap();
+            let privates = vec![
+                ec.private_key_to_pem().unwrap(),
+                ec.private_key_to_der().unwrap(),
+                pkey.private_key_to_pem_pkcs8().unwrap(),
+                pkey.private_key_to_pkcs8().unwrap(),
+            ];
+            let publics = vec![
+                pkey.public_key_to_pem().unwrap(),
+                pkey.public_key_to_der().unwrap(),
+            ];
+            check_all::<B>(Algorithm::ES256, &privates, &publics);
+        }
+
+        fn encrypted<B: Backend>() {
+            let pkey = issue("Signer", None, false).key;
+            let pem = pkey
+                .private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), b"password")
+                .unwrap();
+            let der = pkey
+                .private_key_to_pkcs8_passphrase(Cipher::aes_256_cbc(), b"password")
+                .unwrap();
+
+            for encrypted in &[pem, der] {
+                assert!(B::sign(DATA, encrypted, &Algorithm::RS256).is_err());
+                assert!(B::decrypt_private_key(encrypted, b"wrong").is_err());
+
+                let key = B::decrypt_private_key(encrypted, b"password").unwrap();
+                assert_eq!(pkey.private_key_to_pkcs8().unwrap(), &*key);
+            }
+        }
+
+        #[test]
+        fn openssl_rsa() {
+            rsa::<OpenSsl>();
+        }
+
+        #[test]
+        fn openssl_ec() {
+            ec::<OpenSsl>();
+        }
+
+        #[test]
+        fn openssl_encrypted() {
+            encrypted::<OpenSsl>();
+        }
+
+        #[cfg(feature = "rust-crypto")]
+        #[test]
+        fn rust_crypto_rsa() {
+            rsa::<RustCrypto>();
+        }
+
+        #[cfg(feature = "rust-crypto")]
+        #[test]
+        fn rust_crypto_ec() {
+            ec::<RustCrypto>();
+        }
+
+        #[cfg(feature = "rust-crypto")]
+        #[test]
+        fn rust_crypto_encrypted() {
+            encrypted::<RustCrypto>();
+        }
+    }
+
+    #[cfg(all(feature = "openssl", feature = "rust-crypto"))]
+    mod cross_backend {
+        use super::super::{Backend, OpenSsl, RustCrypto};
+        use crate::header::Algorithm::{self, *};
+        use openssl::{
+            ec::{EcGroup, EcKey},
+            nid::Nid,
+            pkey::PKey,
+            rsa::Rsa,
+        };
+
+        const DATA: &[u8] = b"eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiIxMjM0NTY3ODkwIn0";
+
+        /// Generates a private and public key pair in the PEM formats each backend accepts.
+        fn keys(algorithm: &Algorithm) -> (Vec<u8>, Vec<u8>) {
+            let pkey = match *algorithm {
+                HS256 | HS384 | HS512 => {
+                    let secret = b"a shared secret that is long enough for HS512 use!!!!!!!!!!!!!!";
+                    return (secret.to_vec(), secret.to_vec());
+                }
+                RS256 | RS384 | RS512 => PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap(),
+                ES256 => ec_key(Nid::X9_62_PRIME256V1),
+                ES384 => ec_key(Nid::SECP384R1),
+                ES512 => ec_key(Nid::SECP521R1),
+                EdDSA => PKey::generate_ed25519().unwrap(),
+            };
+            (
+                pkey.private_key_to_pem_pkcs8().unwrap(),
+                pkey.public_key_to_pem().unwrap(),
+            )
+        }
+
+        fn ec_key(curve: Nid) -> PKey<openssl::pkey::Private> {
+            let group = EcGroup::from_curve_name(curve).unwrap();
+            PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+        }
+
+        fn all() -> Vec<Algorithm> {
+            vec![
+                HS256, HS384, HS512, RS256, RS384, RS512, ES256, ES384, ES512, EdDSA,
+            ]
+        }
+
+        #[test]
+        fn openssl_signs_rust_crypto_verifies() {
+            for algorithm in all() {
+                let (private, public) = keys(&algorithm);
+                let sig = OpenSsl::sign(DATA, &private, &algorithm).unwrap();
+
+                assert!(
+                    RustCrypto::verify(&sig, DATA, &public, &algorithm).unwrap(),
+                    "{:?}",
+                    algorithm
+                );
+                assert!(!RustCrypto::verify(&sig, b"tampered", &public, &algorithm).unwrap());
+            }
+        }
+
+        #[test]
+        fn rust_crypto_signs_openssl_verifies() {
+            for algorithm in all() {
+                let (private, public) = keys(&algorithm);
+                let sig = RustCrypto::sign(DATA, &private, &algorithm).unwrap();
+
+                assert!(
+                    OpenSsl::verify(&sig, DATA, &public, &algorithm).unwrap(),
+                    "{:?}",
+                    algorithm
+                );
+                assert!(!OpenSsl::verify(&sig, b"tampered", &public, &algorithm).unwrap());
+            }
+        }
+
+        #[test]
+        fn same_jwk() {
+            for algorithm in all().into_iter().skip(3) {
+                let (private, public) = keys(&algorithm);
+
+                assert_eq!(
+                    OpenSsl::jwk(&private, &algorithm, true).unwrap(),
+                    RustCrypto::jwk(&private, &algorithm, true).unwrap()
+                );
+                assert_eq!(
+                    OpenSsl::jwk(&public, &algorithm, false).unwrap(),
+                    RustCrypto::jwk(&public, &algorithm, false).unwrap()
+                );
+                assert!(OpenSsl::jwk(&public, &algorithm, true).is_err());
+                assert!(RustCrypto::jwk(&public, &algorithm, true).is_err());
+            }
+        }
+
+        #[test]
+        fn generated_keys_cross_backend() {
+            for algorithm in all().into_iter().skip(3) {
+                let (private, public) = OpenSsl::generate(&algorithm).unwrap();
+                let sig = OpenSsl::sign(DATA, &private, &algorithm).unwrap();
+                assert!(RustCrypto::verify(&sig, DATA, &public, &algorithm).unwrap());
+            }
+            for &algorithm in &[RS256, ES256, ES384, ES512, EdDSA] {
+                let (private, public) = RustCrypto::generate(&algorithm).unwrap();
+                let sig = RustCrypto::sign(DATA, &private, &algorithm).unwrap();
+                assert!(OpenSsl::verify(&sig, DATA, &public, &algorithm).unwrap());
+            }
+        }
+
+        #[test]
+        fn rejects_key_for_other_curve() {
+            let (private, _) = keys(&ES384);
+            assert!(OpenSsl::sign(DATA, &private, &ES256).is_err());
+            assert!(RustCrypto::sign(DATA, &private, &ES256).is_err());
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use super::{b64, b64_secret, curve_name, Backend};
+use crate::{header::Algorithm, jwk::Jwk, secret::SecretKey, Result};
+use anyhow::format_err;
+use openssl::{
+    bn::{BigNum, BigNumContext, BigNumRef},
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    memcmp,
+    nid::Nid,
+    pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public},
+    rand::rand_bytes,
+    rsa::Rsa,
+    sha::sha256,
+    sign::{Signer, Verifier},
+    x509::X509,
+};
+use zeroize::Zeroizing;
+
+/// Backend built on the system's OpenSSL, the crate's original and default implementation.
+pub struct OpenSsl;
+
+impl Backend for OpenSsl {
+    fn sign(data: &[u8], key: &[u8], algorithm: &Algorithm) -> Result<Vec<u8>> {
+        match *algorithm {
+            Algorithm::HS256 => sign_hmac(data, key, MessageDigest::sha256()),
+            Algorithm::HS384 => sign_hmac(data, key, MessageDigest::sha384()),
+            Algorithm::HS512 => sign_hmac(data, key, MessageDigest::sha512()),
+            Algorithm::RS256 => sign_rsa(data, key, MessageDigest::sha256()),
+            Algorithm::RS384 => sign_rsa(data, key, MessageDigest::sha384()),
+            Algorithm::RS512 => sign_rsa(data, key, MessageDigest::sha512()),
+            Algorithm::ES256 => {
+                sign_ecdsa(data, key, Nid::X9_62_PRIME256V1, MessageDigest::sha256())
+            }
+            Algorithm::ES384 => sign_ecdsa(data, key, Nid::SECP384R1, MessageDigest::sha384()),
+            Algorithm::ES512 => sign_ecdsa(data, key, Nid::SECP521R1, MessageDigest::sha512()),
+            Algorithm::EdDSA => sign_eddsa(data, key),
+        }
+    }
+
+    fn verify(signature: &[u8], data: &[u8], key: &[u8], algorithm: &Algorithm) -> Result<bool> {
+        match *algorithm {
+            Algorithm::HS256 => verify_hmac(signature, data, key, MessageDigest::sha256()),
+            Algorithm::HS384 => verify_hmac(signature, data, key, MessageDigest::sha384()),
+            Algorithm::HS512 => verify_hmac(signature, data, key, MessageDigest::sha512()),
+            Algorithm::RS256 => verify_rsa(signature, data, key, MessageDigest::sha256()),
+            Algorithm::RS384 => verify_rsa(signature, data, key, MessageDigest::sha384()),
+            Algorithm::RS512 => verify_rsa(signature, data, key, MessageDigest::sha512()),
+            Algorithm::ES256 => verify_ecdsa(
+                signature,
+                data,
+                key,
+                Nid::X9_62_PRIME256V1,
+                MessageDigest::sha256(),
+            ),
+            Algorithm::ES384 => verify_ecdsa(
+                signature,
+                data,
+                key,
+                Nid::SECP384R1,
+                MessageDigest::sha384(),
+            ),
+            Algorithm::ES512 => verify_ecdsa(
+                signature,
+                data,
+                key,
+                Nid::SECP521R1,
+                MessageDigest::sha512(),
+            ),
+            Algorithm::EdDSA => verify_eddsa(signature, data, key),
+        }
+    }
+
+    fn decrypt_private_key(key: &[u8], password: &[u8]) -> Result<SecretKey> {
+        let pkey = if is_pem(key) {
+            PKey::private_key_from_pem_passphrase(key, password)?
+        } else {
+            PKey::private_key_from_pkcs8_passphrase(key, password)?
+        };
+        Ok(pkey.private_key_to_pkcs8()?.into())
+    }
+
+    fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(sha256(data).to_vec())
+    }
+
+    fn rsa_bits(key: &[u8]) -> Result<usize> {
+        // PEM is only read by its label, as OpenSSL may prompt for a passphrase when reading an
+        // encrypted private key as a public one
+        let bits = if is_pem(key) && !contains(key, b"PRIVATE KEY-----") {
+            public_key(key)?.bits()
+        } else {
+            match private_key(key) {
+                Ok(pkey) => pkey.bits(),
+                Err(_) if is_pem(key) => {
+                    return Err(format_err!("Could not read RSA private key."))
+                }
+                Err(_) => public_key(key)?.bits(),
+            }
+        };
+        Ok(bits as usize)
+    }
+
+    fn random(buf: &mut [u8]) -> Result<()> {
+        Ok(rand_bytes(buf)?)
+    }
+
+    fn generate(algorithm: &Algorithm) -> Result<(SecretKey, Vec<u8>)> {
+        let pkey = match *algorithm {
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+                PKey::from_rsa(Rsa::generate(2048)?)?
+            }
+            Algorithm::EdDSA => PKey::generate_ed25519()?,
+            _ => {
+                let group = EcGroup::from_curve_name(ec_curve(algorithm)?)?;
+                PKey::from_ec_key(EcKey::generate(&group)?)?
+            }
+        };
+        Ok((
+            pkey.private_key_to_pkcs8()?.into(),
+            pkey.public_key_to_der()?,
+        ))
+    }
+
+    fn jwk(key: &[u8], algorithm: &Algorithm, private: bool) -> Result<Jwk> {
+        match private_key(key) {
+            Ok(pkey) if private => private_jwk(&pkey, algorithm),
+            Ok(pkey) => public_jwk(&pkey, algorithm),
+            Err(_) if !private => {
+                let pkey = public_key(key)?;
+                public_jwk(&pkey, algorithm)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn sign_hmac(data: &[u8], key: &[u8], digest: MessageDigest) -> Result<Vec<u8>> {
+    let secret_key = PKey::hmac(key)?;
+
+    let mut signer = Signer::new(digest, &secret_key)?;
+    signer.update(data)?;
+
+    Ok(signer.sign_to_vec()?)
+}
+
+fn sign_rsa(data: &[u8], key: &[u8], digest: MessageDigest) -> Result<Vec<u8>> {
+    let pkey = private_key(key)?;
+    check_id(&pkey, Id::RSA)?;
+
+    let mut signer = Signer::new(digest, &pkey)?;
+    signer.update(data)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+fn sign_ecdsa(data: &[u8], key: &[u8], curve: Nid, digest: MessageDigest) -> Result<Vec<u8>> {
+    let pkey = private_key(key)?;
+    let width = check_curve(&pkey, curve)?;
+
+    let mut signer = Signer::new(digest, &pkey)?;
+    signer.update(data)?;
+    let der = signer.sign_to_vec()?;
+
+    // OpenSSL produces a DER sequence, JWS wants the two integers concatenated at a fixed width
+    let sig = EcdsaSig::from_der(&der)?;
+    let mut raw = sig.r().to_vec_padded(width)?;
+    raw.extend(sig.s().to_vec_padded(width)?);
+    Ok(raw)
+}
+
+fn sign_eddsa(data: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    let pkey = private_key(key)?;
+    check_id(&pkey, Id::ED25519)?;
+
+    let mut signer = Signer::new_without_digest(&pkey)?;
+    Ok(signer.sign_oneshot_to_vec(data)?)
+}
+
+fn verify_hmac(target: &[u8], data: &[u8], key: &[u8], digest: MessageDigest) -> Result<bool> {
+    let mac = sign_hmac(data, key, digest)?;
+
+    Ok(mac.len() == target.len() && memcmp::eq(&mac, target))
+}
+
+fn verify_rsa(signature: &[u8], data: &[u8], key: &[u8], digest: MessageDigest) -> Result<bool> {
+    let pkey = public_key(key)?;
+    check_id(&pkey, Id::RSA)?;
+    let mut verifier = Verifier::new(digest, &pkey)?;
+    verifier.update(data)?;
+    Ok(verifier.verify(signature)?)
+}
+
+fn verify_ecdsa(
+    signature: &[u8],
+    data: &[u8],
+    key: &[u8],
+    curve: Nid,
+    digest: MessageDigest,
+) -> Result<bool> {
+    let pkey = public_key(key)?;
+    let width = check_curve(&pkey, curve)?;
+    if signature.len() != width as usize * 2 {
+        return Ok(false);
+    }
+
+    let (r, s) = signature.split_at(width as usize);
+    let sig = EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?;
+
+    let mut verifier = Verifier::new(digest, &pkey)?;
+    verifier.update(data)?;
+    Ok(verifier.verify(&sig.to_der()?)?)
+}
+
+fn verify_eddsa(signature: &[u8], data: &[u8], key: &[u8]) -> Result<bool> {
+    let pkey = public_key(key)?;
+    check_id(&pkey, Id::ED25519)?;
+
+    let mut verifier = Verifier::new_without_digest(&pkey)?;
+    Ok(verifier.verify_oneshot(signature, data)?)
+}
+
+fn public_jwk<T: HasPublic>(pkey: &PKeyRef<T>, algorithm: &Algorithm) -> Result<Jwk> {
+    match *algorithm {
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+            check_id(pkey, Id::RSA)?;
+            let rsa = pkey.rsa()?;
+            Ok(Jwk {
+                kty: "RSA".into(),
+                n: Some(b64(&rsa.n().to_vec())),
+                e: Some(b64(&rsa.e().to_vec())),
+                ..Jwk::default()
+            })
+        }
+        Algorithm::EdDSA => {
+            check_id(pkey, Id::ED25519)?;
+            Ok(Jwk {
+                kty: "OKP".into(),
+                crv: curve_name(algorithm).map(Into::into),
+                x: Some(b64(&pkey.raw_public_key()?)),
+                ..Jwk::default()
+            })
+        }
+        _ => {
+            let width = check_curve(pkey, ec_curve(algorithm)?)?;
+            let ec = pkey.ec_key()?;
+            let (mut x, mut y) = (BigNum::new()?, BigNum::new()?);
+            let mut context = BigNumContext::new()?;
+            ec.public_key()
+                .affine_coordinates(ec.group(), &mut x, &mut y, &mut context)?;
+            Ok(Jwk {
+                kty: "EC".into(),
+                crv: curve_name(algorithm).map(Into::into),
+                x: Some(b64(&x.to_vec_padded(width)?)),
+                y: Some(b64(&y.to_vec_padded(width)?)),
+                ..Jwk::default()
+            })
+        }
+    }
+}
+
+fn private_jwk(pkey: &PKey<Private>, algorithm: &Algorithm) -> Result<Jwk> {
+    let public = public_jwk(pkey, algorithm)?;
+    let encode = |n: Option<&BigNumRef>| n.map(|n| b64_secret(&Zeroizing::new(n.to_vec())));
+    match *algorithm {
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+            let rsa = pkey.rsa()?;
+            Ok(Jwk {
+                d: encode(Some(rsa.d())),
+                p: encode(rsa.p()),
+                q: encode(rsa.q()),
+                dp: encode(rsa.dmp1()),
+                dq: encode(rsa.dmq1()),
+                qi: encode(rsa.iqmp()),
+                ..public
+            })
+        }
+        Algorithm::EdDSA => Ok(Jwk {
+            d: Some(b64_secret(&Zeroizing::new(pkey.raw_private_key()?))),
+            ..public
+        }),
+        _ => {
+            let width = check_curve(pkey, ec_curve(algorithm)?)?;
+            Ok(Jwk {
+                d: Some(b64_secret(&Zeroizing::new(
+                    pkey.ec_key()?.private_key().to_vec_padded(width)?,
+                ))),
+                ..public
+            })
+        }
+    }
+}
+
+fn ec_curve(algorithm: &Algorithm) -> Result<Nid> {
+    match *algorithm {
+        Algorithm::ES256 => Ok(Nid::X9_62_PRIME256V1),
+        Algorithm::ES384 => Ok(Nid::SECP384R1),
+        Algorithm::ES512 => Ok(Nid::SECP521R1),
+        _ => Err(format_err!(
+            "{:?} is not an elliptic curve algorithm.",
+            algorithm
+        )),
+    }
+}
+
+/// Reads an unencrypted private key, PEM or DER, in any of the PKCS#1, PKCS#8 or SEC1 structures.
+fn private_key(key: &[u8]) -> Result<PKey<Private>> {
+    if is_pem(key) {
+        // an explicit, empty passphrase keeps OpenSSL from prompting on the terminal for a key
+        // that turns out to be encrypted
+        Ok(PKey::private_key_from_pem_passphrase(key, b"")?)
+    } else {
+        Ok(PKey::private_key_from_der(key)?)
+    }
+}
+
+/// Reads a public key, PEM or DER, either as a SubjectPublicKeyInfo, a PKCS#1 RSA public key or
+/// the key in an X.509 certificate.
+fn public_key(key: &[u8]) -> Result<PKey<Public>> {
+    if is_pem(key) {
+        if contains(key, b"-----BEGIN CERTIFICATE-----") {
+            Ok(X509::from_pem(key)?.public_key()?)
+        } else if contains(key, b"-----BEGIN RSA PUBLIC KEY-----") {
+            Ok(PKey::from_rsa(Rsa::public_key_from_pem_pkcs1(key)?)?)
+        } else {
+            Ok(PKey::public_key_from_pem(key)?)
+        }
+    } else {
+        PKey::public_key_from_der(key)
+            .or_else(|_| X509::from_der(key).and_then(|cert| cert.public_key()))
+            .or_else(|_| Rsa::public_key_from_der_pkcs1(key).and_then(PKey::from_rsa))
+            .map_err(|_| format_err!("Could not read public key."))
+    }
+}
+
+fn is_pem(key: &[u8]) -> bool {
+    contains(key, b"-----BEGIN ")
+}
+
+fn contains(key: &[u8], marker: &[u8]) -> bool {
+    key.windows(marker.len()).any(|window| window == marker)
+}
+
+fn check_id<T>(pkey: &PKeyRef<T>, id: Id) -> Result<()> {
+    if pkey.id() == id {
+        Ok(())
+    } else {
+        Err(format_err!("Key type does not match the algorithm."))
+    }
+}
+
+/// Makes sure an EC key is on the curve the algorithm requires, returning the width in bytes of
+/// each of the signature's integers.
+fn check_curve<T: HasPublic>(pkey: &PKeyRef<T>, curve: Nid) -> Result<i32> {
+    check_id(pkey, Id::EC)?;
+    let group = pkey.ec_key()?.group().curve_name();
+    if group != Some(curve) {
+        return Err(format_err!(
+            "EC key is not on the curve the algorithm requires."
+        ));
+    }
+    Ok(match curve {
+        Nid::X9_62_PRIME256V1 => 32,
+        Nid::SECP384R1 => 48,
+        _ => 66,
+    })
+}
@@ -0,0 +1,406 @@
+use super::{b64, b64_secret, curve_name, Backend};
+use crate::{header::Algorithm, jwk::Jwk, secret::SecretKey, Result};
+use anyhow::format_err;
+use hmac::{Mac, SimpleHmac};
+use pkcs8::{
+    der::{pem, Decode, Encode},
+    DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, EncryptedPrivateKeyInfo,
+};
+use rand_core::{OsRng, RngCore};
+use rsa::{
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    pkcs1v15,
+    signature::{SignatureEncoding, Signer, Verifier},
+    traits::{PrivateKeyParts, PublicKeyParts},
+    BigUint, RsaPrivateKey, RsaPublicKey,
+};
+use sha2::{
+    digest::{const_oid::AssociatedOid, core_api::BlockSizeUser, Digest, FixedOutputReset},
+    Sha256, Sha384, Sha512,
+};
+use std::convert::TryFrom;
+use x509_cert::Certificate;
+use zeroize::Zeroizing;
+
+/// Backend built only on pure Rust crates, so that it needs no system libraries and can target
+/// WebAssembly.
+pub struct RustCrypto;
+
+impl Backend for RustCrypto {
+    fn sign(data: &[u8], key: &[u8], algorithm: &Algorithm) -> Result<Vec<u8>> {
+        match *algorithm {
+            Algorithm::HS256 => sign_hmac::<Sha256>(data, key),
+            Algorithm::HS384 => sign_hmac::<Sha384>(data, key),
+            Algorithm::HS512 => sign_hmac::<Sha512>(data, key),
+            Algorithm::RS256 => sign_rsa::<Sha256>(data, key),
+            Algorithm::RS384 => sign_rsa::<Sha384>(data, key),
+            Algorithm::RS512 => sign_rsa::<Sha512>(data, key),
+            Algorithm::ES256 => sign_p256(data, key),
+            Algorithm::ES384 => sign_p384(data, key),
+            Algorithm::ES512 => sign_p521(data, key),
+            Algorithm::EdDSA => sign_eddsa(data, key),
+        }
+    }
+
+    fn verify(signature: &[u8], data: &[u8], key: &[u8], algorithm: &Algorithm) -> Result<bool> {
+        match *algorithm {
+            Algorithm::HS256 => verify_hmac::<Sha256>(signature, data, key),
+            Algorithm::HS384 => verify_hmac::<Sha384>(signature, data, key),
+            Algorithm::HS512 => verify_hmac::<Sha512>(signature, data, key),
+            Algorithm::RS256 => verify_rsa::<Sha256>(signature, data, key),
+            Algorithm::RS384 => verify_rsa::<Sha384>(signature, data, key),
+            Algorithm::RS512 => verify_rsa::<Sha512>(signature, data, key),
+            Algorithm::ES256 => verify_p256(signature, data, key),
+            Algorithm::ES384 => verify_p384(signature, data, key),
+            Algorithm::ES512 => verify_p521(signature, data, key),
+            Algorithm::EdDSA => verify_eddsa(signature, data, key),
+        }
+    }
+
+    fn decrypt_private_key(key: &[u8], password: &[u8]) -> Result<SecretKey> {
+        let der = decode(key)?.1;
+        let info = EncryptedPrivateKeyInfo::from_der(&der)
+            .map_err(|_| format_err!("Key is not an encrypted PKCS#8 private key."))?;
+        let document = info
+            .decrypt(password)
+            .map_err(|_| format_err!("Could not decrypt private key."))?;
+        Ok(document.as_bytes().into())
+    }
+
+    fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(Sha256::digest(data).to_vec())
+    }
+
+    fn rsa_bits(key: &[u8]) -> Result<usize> {
+        let der = decode(key)?.1;
+        if let Ok(private_key) =
+            RsaPrivateKey::from_pkcs8_der(&der).or_else(|_| RsaPrivateKey::from_pkcs1_der(&der))
+        {
+            return Ok(private_key.n().bits());
+        }
+        let der = public_der(key)?;
+        let public_key = RsaPublicKey::from_public_key_der(&der)
+            .or_else(|_| RsaPublicKey::from_pkcs1_der(&der))
+            .map_err(|_| format_err!("Could not read RSA key."))?;
+        Ok(public_key.n().bits())
+    }
+
+    fn random(buf: &mut [u8]) -> Result<()> {
+        OsRng
+            .try_fill_bytes(buf)
+            .map_err(|_| format_err!("Could not get random bytes from the system."))
+    }
+
+    fn generate(algorithm: &Algorithm) -> Result<(SecretKey, Vec<u8>)> {
+        match *algorithm {
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+                let private_key = RsaPrivateKey::new(&mut OsRng, 2048)?;
+                let public_key = RsaPublicKey::from(&private_key);
+                Ok((
+                    private_key
+                        .to_pkcs8_der()
+                        .map_err(unencodable)?
+                        .as_bytes()
+                        .into(),
+                    public_key
+                        .to_public_key_der()
+                        .map_err(unencodable)?
+                        .into_vec(),
+                ))
+            }
+            Algorithm::ES256 => generate_p256(),
+            Algorithm::ES384 => generate_p384(),
+            Algorithm::ES512 => generate_p521(),
+            Algorithm::EdDSA => {
+                let mut secret = Zeroizing::new([0; ed25519_dalek::SECRET_KEY_LENGTH]);
+                Self::random(&mut *secret)?;
+                let signing_key = ed25519_dalek::SigningKey::from_bytes(&secret);
+                Ok((
+                    signing_key
+                        .to_pkcs8_der()
+                        .map_err(unencodable)?
+                        .as_bytes()
+                        .into(),
+                    signing_key
+                        .verifying_key()
+                        .to_public_key_der()
+                        .map_err(unencodable)?
+                        .into_vec(),
+                ))
+            }
+            _ => Err(format_err!("{:?} does not use a key pair.", algorithm)),
+        }
+    }
+
+    fn jwk(key: &[u8], algorithm: &Algorithm, private: bool) -> Result<Jwk> {
+        match *algorithm {
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => rsa_jwk(key, private),
+            Algorithm::ES256 => jwk_p256(key, algorithm, private),
+            Algorithm::ES384 => jwk_p384(key, algorithm, private),
+            Algorithm::ES512 => jwk_p521(key, algorithm, private),
+            Algorithm::EdDSA => eddsa_jwk(key, algorithm, private),
+            _ => Err(format_err!("{:?} does not use a key pair.", algorithm)),
+        }
+    }
+}
+
+fn sign_hmac<D>(data: &[u8], key: &[u8]) -> Result<Vec<u8>>
+where
+    D: Digest + BlockSizeUser,
+{
+    let mut mac = SimpleHmac::<D>::new_from_slice(key)?;
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+fn verify_hmac<D>(signature: &[u8], data: &[u8], key: &[u8]) -> Result<bool>
+where
+    D: Digest + BlockSizeUser,
+{
+    let mut mac = SimpleHmac::<D>::new_from_slice(key)?;
+    mac.update(data);
+    // compares in constant time
+    Ok(mac.verify_slice(signature).is_ok())
+}
+
+fn sign_rsa<D>(data: &[u8], key: &[u8]) -> Result<Vec<u8>>
+where
+    D: Digest + AssociatedOid + FixedOutputReset,
+{
+    let der = private_der(key)?;
+    let private_key = RsaPrivateKey::from_pkcs8_der(&der)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_der(&der))
+        .map_err(|_| format_err!("Could not read RSA private key."))?;
+
+    let signing_key = pkcs1v15::SigningKey::<D>::new(private_key);
+    Ok(signing_key.sign(data).to_vec())
+}
+
+fn verify_rsa<D>(signature: &[u8], data: &[u8], key: &[u8]) -> Result<bool>
+where
+    D: Digest + AssociatedOid,
+{
+    let der = public_der(key)?;
+    let public_key = RsaPublicKey::from_public_key_der(&der)
+        .or_else(|_| RsaPublicKey::from_pkcs1_der(&der))
+        .map_err(|_| format_err!("Could not read RSA public key."))?;
+
+    let verifying_key = pkcs1v15::VerifyingKey::<D>::new(public_key);
+    let signature = match pkcs1v15::Signature::try_from(signature) {
+        Ok(signature) => signature,
+        Err(_) => return Ok(false),
+    };
+    Ok(verifying_key.verify(data, &signature).is_ok())
+}
+
+/// Each curve has its own crate with identically shaped types, so the ECDSA functions are stamped
+/// out per curve rather than written generically over `elliptic_curve`'s many bounds.
+macro_rules! ecdsa {
+    ($sign:ident, $verify:ident, $curve:ident) => {
+        fn $sign(data: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+            let der = private_der(key)?;
+            let secret = $curve::SecretKey::from_pkcs8_der(&der)
+                .or_else(|_| $curve::SecretKey::from_sec1_der(&der))
+                .map_err(|_| format_err!("Could not read EC private key for this curve."))?;
+
+            let signing_key = $curve::ecdsa::SigningKey::from_bytes(&secret.to_bytes())?;
+            let signature: $curve::ecdsa::Signature = signing_key.sign(data);
+            Ok(signature.to_bytes().to_vec())
+        }
+
+        fn $verify(signature: &[u8], data: &[u8], key: &[u8]) -> Result<bool> {
+            let public = $curve::PublicKey::from_public_key_der(&public_der(key)?)
+                .map_err(|_| format_err!("Could not read EC public key for this curve."))?;
+
+            let verifying_key = $curve::ecdsa::VerifyingKey::from_affine(*public.as_affine())?;
+            let signature = match $curve::ecdsa::Signature::from_slice(signature) {
+                Ok(signature) => signature,
+                Err(_) => return Ok(false),
+            };
+            Ok(verifying_key.verify(data, &signature).is_ok())
+        }
+    };
+}
+
+ecdsa!(sign_p256, verify_p256, p256);
+ecdsa!(sign_p384, verify_p384, p384);
+ecdsa!(sign_p521, verify_p521, p521);
+
+/// Key generation and JWK conversion for each curve, stamped out like the signatures.
+macro_rules! ec_keys {
+    ($generate:ident, $jwk:ident, $curve:ident) => {
+        fn $generate() -> Result<(SecretKey, Vec<u8>)> {
+            let secret = $curve::SecretKey::random(&mut OsRng);
+            Ok((
+                secret
+                    .to_pkcs8_der()
+                    .map_err(unencodable)?
+                    .as_bytes()
+                    .into(),
+                secret
+                    .public_key()
+                    .to_public_key_der()
+                    .map_err(unencodable)?
+                    .into_vec(),
+            ))
+        }
+
+        fn $jwk(key: &[u8], algorithm: &Algorithm, private: bool) -> Result<Jwk> {
+            use $curve::elliptic_curve::sec1::ToEncodedPoint;
+
+            let der = decode(key)?.1;
+            let secret = $curve::SecretKey::from_pkcs8_der(&der)
+                .or_else(|_| $curve::SecretKey::from_sec1_der(&der))
+                .ok();
+            let public = match secret {
+                Some(ref secret) => secret.public_key(),
+                None if !private => $curve::PublicKey::from_public_key_der(&public_der(key)?)
+                    .map_err(|_| format_err!("Could not read EC public key for this curve."))?,
+                None => return Err(format_err!("Could not read EC private key for this curve.")),
+            };
+
+            let point = public.to_encoded_point(false);
+            Ok(Jwk {
+                kty: "EC".into(),
+                crv: curve_name(algorithm).map(Into::into),
+                x: point.x().map(|x| b64(x)),
+                y: point.y().map(|y| b64(y)),
+                d: secret
+                    .filter(|_| private)
+                    .map(|secret| b64_secret(&secret.to_bytes())),
+                ..Jwk::default()
+            })
+        }
+    };
+}
+
+ec_keys!(generate_p256, jwk_p256, p256);
+ec_keys!(generate_p384, jwk_p384, p384);
+ec_keys!(generate_p521, jwk_p521, p521);
+
+fn rsa_jwk(key: &[u8], private: bool) -> Result<Jwk> {
+    let der = decode(key)?.1;
+    let private_key = RsaPrivateKey::from_pkcs8_der(&der)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_der(&der))
+        .ok();
+    let public_key = match private_key {
+        Some(ref private_key) => RsaPublicKey::from(private_key),
+        None if !private => {
+            let der = public_der(key)?;
+            RsaPublicKey::from_public_key_der(&der)
+                .or_else(|_| RsaPublicKey::from_pkcs1_der(&der))
+                .map_err(|_| format_err!("Could not read RSA public key."))?
+        }
+        None => return Err(format_err!("Could not read RSA private key.")),
+    };
+
+    let encode = |n: &BigUint| b64(&n.to_bytes_be());
+    let secret = |n: &BigUint| b64_secret(&Zeroizing::new(n.to_bytes_be()));
+    let public = Jwk {
+        kty: "RSA".into(),
+        n: Some(encode(public_key.n())),
+        e: Some(encode(public_key.e())),
+        ..Jwk::default()
+    };
+    match private_key {
+        Some(private_key) if private => {
+            let primes = private_key.primes();
+            Ok(Jwk {
+                d: Some(secret(private_key.d())),
+                p: primes.first().map(secret),
+                q: primes.get(1).map(secret),
+                dp: private_key.dp().map(secret),
+                dq: private_key.dq().map(secret),
+                qi: private_key.crt_coefficient().as_ref().map(secret),
+                ..public
+            })
+        }
+        _ => Ok(public),
+    }
+}
+
+fn eddsa_jwk(key: &[u8], algorithm: &Algorithm, private: bool) -> Result<Jwk> {
+    let signing_key = ed25519_dalek::SigningKey::from_pkcs8_der(&decode(key)?.1).ok();
+    let verifying_key = match signing_key {
+        Some(ref signing_key) => signing_key.verifying_key(),
+        None if !private => ed25519_dalek::VerifyingKey::from_public_key_der(&public_der(key)?)
+            .map_err(|_| format_err!("Could not read Ed25519 public key."))?,
+        None => return Err(format_err!("Could not read Ed25519 private key.")),
+    };
+
+    Ok(Jwk {
+        kty: "OKP".into(),
+        crv: curve_name(algorithm).map(Into::into),
+        x: Some(b64(verifying_key.as_bytes())),
+        d: signing_key
+            .filter(|_| private)
+            .map(|signing_key| b64_secret(signing_key.as_bytes())),
+        ..Jwk::default()
+    })
+}
+
+fn unencodable<E>(_: E) -> anyhow::Error {
+    format_err!("Could not encode generated key.")
+}
+
+fn sign_eddsa(data: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    let signing_key = ed25519_dalek::SigningKey::from_pkcs8_der(&private_der(key)?)
+        .map_err(|_| format_err!("Could not read Ed25519 private key."))?;
+
+    Ok(signing_key.sign(data).to_vec())
+}
+
+fn verify_eddsa(signature: &[u8], data: &[u8], key: &[u8]) -> Result<bool> {
+    let verifying_key = ed25519_dalek::VerifyingKey::from_public_key_der(&public_der(key)?)
+        .map_err(|_| format_err!("Could not read Ed25519 public key."))?;
+
+    let signature = match ed25519_dalek::Signature::from_slice(signature) {
+        Ok(signature) => signature,
+        Err(_) => return Ok(false),
+    };
+    Ok(verifying_key.verify(data, &signature).is_ok())
+}
+
+/// Strips the PEM encoding from a key, if it has one, returning the label along with the DER.
+/// The DER structures are then told apart by trying to parse each in turn.
+fn decode(key: &[u8]) -> Result<(Option<String>, SecretKey)> {
+    let trimmed = match key.iter().position(|b| !b.is_ascii_whitespace()) {
+        Some(start) => &key[start..],
+        None => key,
+    };
+    if trimmed.starts_with(b"-----BEGIN ") {
+        let (label, der) =
+            pem::decode_vec(trimmed).map_err(|_| format_err!("Could not read PEM encoded key."))?;
+        Ok((Some(label.to_owned()), der.into()))
+    } else {
+        Ok((None, key.into()))
+    }
+}
+
+fn private_der(key: &[u8]) -> Result<SecretKey> {
+    Ok(decode(key)?.1)
+}
+
+/// Produces the DER for a public key, taking the SubjectPublicKeyInfo out of an X.509
+/// certificate when given one.
+fn public_der(key: &[u8]) -> Result<Vec<u8>> {
+    let (label, der) = decode(key)?;
+    let is_cert = match label {
+        Some(ref label) => label == "CERTIFICATE",
+        None => pkcs8::SubjectPublicKeyInfoRef::from_der(&der).is_err(),
+    };
+    if !is_cert {
+        return Ok(der.to_vec());
+    }
+
+    match Certificate::from_der(&der) {
+        Ok(cert) => cert
+            .tbs_certificate
+            .subject_public_key_info
+            .to_der()
+            .map_err(|_| format_err!("Could not read certificate's public key.")),
+        // could still be a PKCS#1 RSA public key
+        Err(_) if label.is_none() => Ok(der.to_vec()),
+        Err(_) => Err(format_err!("Could not read certificate.")),
+    }
+}
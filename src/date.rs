@@ -0,0 +1,265 @@
+use crate::error::Error;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{
+    de::{self, Deserializer, Visitor},
+    Deserialize, Serialize, Serializer,
+};
+use std::{
+    convert::TryFrom,
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// An RFC 7519 NumericDate, the seconds since the Unix epoch used by `exp`, `nbf` and `iat`. The
+/// seconds may be fractional or negative, but must fall within the dates `chrono` can represent,
+/// so every `NumericDate` converts to a `DateTime<Utc>`. Whole seconds are written as an integer,
+/// anything else as a decimal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NumericDate {
+    // the fraction is always a positive number of nanoseconds past secs, even before the epoch
+    secs: i64,
+    nanos: u32,
+}
+
+impl NumericDate {
+    pub fn from_secs(secs: i64) -> Result<NumericDate, Error> {
+        NumericDate::new(secs, 0)
+    }
+
+    fn new(secs: i64, nanos: u32) -> Result<NumericDate, Error> {
+        match Utc.timestamp_opt(secs, nanos).single() {
+            Some(_) if nanos < NANOS_PER_SEC => Ok(NumericDate { secs, nanos }),
+            _ => Err(Error::InvalidNumericDate),
+        }
+    }
+
+    /// The whole seconds since the epoch, rounded down.
+    pub fn secs(&self) -> i64 {
+        self.secs
+    }
+
+    pub fn subsec_nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    pub fn to_datetime(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.secs, self.nanos)
+            .single()
+            .expect("checked to be in range when created")
+    }
+}
+
+impl From<DateTime<Utc>> for NumericDate {
+    fn from(date: DateTime<Utc>) -> NumericDate {
+        NumericDate {
+            secs: date.timestamp(),
+            nanos: date.timestamp_subsec_nanos() % NANOS_PER_SEC,
+        }
+    }
+}
+
+impl From<NumericDate> for DateTime<Utc> {
+    fn from(date: NumericDate) -> DateTime<Utc> {
+        date.to_datetime()
+    }
+}
+
+impl TryFrom<i64> for NumericDate {
+    type Error = Error;
+
+    fn try_from(secs: i64) -> Result<NumericDate, Error> {
+        NumericDate::from_secs(secs)
+    }
+}
+
+impl TryFrom<u64> for NumericDate {
+    type Error = Error;
+
+    fn try_from(secs: u64) -> Result<NumericDate, Error> {
+        let secs = i64::try_from(secs).map_err(|_| Error::InvalidNumericDate)?;
+        NumericDate::from_secs(secs)
+    }
+}
+
+impl TryFrom<f64> for NumericDate {
+    type Error = Error;
+
+    fn try_from(secs: f64) -> Result<NumericDate, Error> {
+        let whole = secs.floor();
+        // rules out NaN and the infinities as well as values beyond i64
+        if !(whole >= i64::MIN as f64 && whole < i64::MAX as f64) {
+            return Err(Error::InvalidNumericDate);
+        }
+        let nanos = ((secs - whole) * f64::from(NANOS_PER_SEC)).round() as u32;
+        if nanos == NANOS_PER_SEC {
+            NumericDate::new(whole as i64 + 1, 0)
+        } else {
+            NumericDate::new(whole as i64, nanos)
+        }
+    }
+}
+
+impl TryFrom<SystemTime> for NumericDate {
+    type Error = Error;
+
+    fn try_from(time: SystemTime) -> Result<NumericDate, Error> {
+        let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => (
+                i64::try_from(since.as_secs()).map_err(|_| Error::InvalidNumericDate)?,
+                since.subsec_nanos(),
+            ),
+            Err(before) => {
+                let before = before.duration();
+                let secs =
+                    i64::try_from(before.as_secs()).map_err(|_| Error::InvalidNumericDate)?;
+                match before.subsec_nanos() {
+                    0 => (-secs, 0),
+                    nanos => (-secs - 1, NANOS_PER_SEC - nanos),
+                }
+            }
+        };
+        NumericDate::new(secs, nanos)
+    }
+}
+
+impl TryFrom<NumericDate> for SystemTime {
+    type Error = Error;
+
+    fn try_from(date: NumericDate) -> Result<SystemTime, Error> {
+        let whole = if date.secs >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(date.secs as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(date.secs.unsigned_abs()))
+        };
+        whole
+            .and_then(|whole| whole.checked_add(Duration::from_nanos(u64::from(date.nanos))))
+            .ok_or(Error::InvalidNumericDate)
+    }
+}
+
+impl Serialize for NumericDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.nanos == 0 {
+            serializer.serialize_i64(self.secs)
+        } else {
+            let fraction = f64::from(self.nanos) / f64::from(NANOS_PER_SEC);
+            serializer.serialize_f64(self.secs as f64 + fraction)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for NumericDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<NumericDate, D::Error> {
+        deserializer.deserialize_any(NumericDateVisitor)
+    }
+}
+
+struct NumericDateVisitor;
+
+impl<'de> Visitor<'de> for NumericDateVisitor {
+    type Value = NumericDate;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("seconds since the epoch")
+    }
+
+    fn visit_i64<E: de::Error>(self, secs: i64) -> Result<NumericDate, E> {
+        NumericDate::try_from(secs).map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, secs: u64) -> Result<NumericDate, E> {
+        NumericDate::try_from(secs).map_err(E::custom)
+    }
+
+    fn visit_f64<E: de::Error>(self, secs: f64) -> Result<NumericDate, E> {
+        NumericDate::try_from(secs).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NumericDate;
+    use crate::Error;
+    use chrono::{TimeZone, Utc};
+    use std::{
+        convert::TryFrom,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    fn parse(json: &str) -> serde_json::Result<NumericDate> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn integer_seconds() {
+        let date = parse("1700000000").unwrap();
+
+        assert_eq!(1_700_000_000, date.secs());
+        assert_eq!(0, date.subsec_nanos());
+        assert_eq!("1700000000", serde_json::to_string(&date).unwrap());
+    }
+
+    #[test]
+    fn fractional_seconds() {
+        let date = parse("1700000000.5").unwrap();
+
+        assert_eq!(1_700_000_000, date.secs());
+        assert_eq!(500_000_000, date.subsec_nanos());
+        assert_eq!("1700000000.5", serde_json::to_string(&date).unwrap());
+        assert!(date > parse("1700000000").unwrap());
+        assert!(date < parse("1700000001").unwrap());
+    }
+
+    #[test]
+    fn negative_seconds() {
+        let date = parse("-1.25").unwrap();
+
+        assert_eq!(-2, date.secs());
+        assert_eq!(750_000_000, date.subsec_nanos());
+        assert_eq!("-1.25", serde_json::to_string(&date).unwrap());
+        assert_eq!(
+            Utc.timestamp_opt(-2, 750_000_000).unwrap(),
+            date.to_datetime()
+        );
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(parse("18446744073709551615").is_err());
+        assert!(parse("1e300").is_err());
+        assert!(parse("-9223372036854775808").is_err());
+        assert!(parse("\"1700000000\"").is_err());
+        assert_eq!(
+            Err(Error::InvalidNumericDate),
+            NumericDate::from_secs(i64::MAX)
+        );
+        assert_eq!(
+            Err(Error::InvalidNumericDate),
+            NumericDate::try_from(f64::NAN)
+        );
+    }
+
+    #[test]
+    fn chrono_conversions() {
+        let time = Utc.timestamp_opt(1_700_000_000, 123_456_789).unwrap();
+        let date = NumericDate::from(time);
+
+        assert_eq!(123_456_789, date.subsec_nanos());
+        assert_eq!(time, date.to_datetime());
+    }
+
+    #[test]
+    fn system_time_conversions() {
+        let after = UNIX_EPOCH + Duration::new(1_700_000_000, 5);
+        let date = NumericDate::try_from(after).unwrap();
+        assert_eq!((1_700_000_000, 5), (date.secs(), date.subsec_nanos()));
+        assert_eq!(after, SystemTime::try_from(date).unwrap());
+
+        let before = UNIX_EPOCH - Duration::new(1, 250_000_000);
+        let date = NumericDate::try_from(before).unwrap();
+        assert_eq!((-2, 750_000_000), (date.secs(), date.subsec_nanos()));
+        assert_eq!(before, SystemTime::try_from(date).unwrap());
+    }
+}
@@ -0,0 +1,239 @@
+use super::Result;
+use crate::{
+    error::{json_type, Error},
+    payload::REGISTERED_CLAIMS,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+/// Custom claims whose shape is not known ahead of time, for use as `Payload<DynamicClaims>`. Every
+/// claim other than the registered ones, which stay in the payload's own fields, is kept as JSON
+/// and read with the typed getters, which return `None` for a missing claim and an
+/// `Error::ClaimType` for one of another type.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct DynamicClaims(Map<String, Value>);
+
+impl DynamicClaims {
+    pub fn new() -> DynamicClaims {
+        DynamicClaims::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    pub fn get_str(&self, name: &str) -> Result<Option<&str>> {
+        self.get_typed(name, "a string", Value::as_str)
+    }
+
+    pub fn get_bool(&self, name: &str) -> Result<Option<bool>> {
+        self.get_typed(name, "a boolean", Value::as_bool)
+    }
+
+    pub fn get_i64(&self, name: &str) -> Result<Option<i64>> {
+        self.get_typed(name, "an integer", Value::as_i64)
+    }
+
+    pub fn get_f64(&self, name: &str) -> Result<Option<f64>> {
+        self.get_typed(name, "a number", Value::as_f64)
+    }
+
+    pub fn get_array(&self, name: &str) -> Result<Option<&Vec<Value>>> {
+        self.get_typed(name, "an array", Value::as_array)
+    }
+
+    pub fn get_object(&self, name: &str) -> Result<Option<&Map<String, Value>>> {
+        self.get_typed(name, "an object", Value::as_object)
+    }
+
+    /// Follow a path of claim names separated by periods through nested objects, such as
+    /// `realm_access.roles`. Only the typed getters can reach a claim whose own name has a period
+    /// in it, as URI names often do.
+    pub fn get_path(&self, path: &str) -> Result<Option<&Value>> {
+        let mut names = path.split('.');
+        let first = names.next().unwrap_or_default();
+        let mut value = match self.0.get(first) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        // the end of the part of the path walked so far, to name the claim that is not an object
+        let mut walked = first.len();
+        for name in names {
+            let object = value.as_object().ok_or_else(|| Error::ClaimType {
+                name: path[..walked].into(),
+                expected: "an object",
+                found: json_type(value),
+            })?;
+            value = match object.get(name) {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+            walked += 1 + name.len();
+        }
+        Ok(Some(value))
+    }
+
+    pub fn insert<V: Into<Value>>(&mut self, name: &str, value: V) -> Option<Value> {
+        self.0.insert(name.into(), value.into())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.0.remove(name)
+    }
+
+    pub fn as_map(&self) -> &Map<String, Value> {
+        &self.0
+    }
+
+    pub fn into_map(self) -> Map<String, Value> {
+        self.0
+    }
+
+    fn get_typed<'a, T, F>(
+        &'a self,
+        name: &str,
+        expected: &'static str,
+        read: F,
+    ) -> Result<Option<T>>
+    where
+        F: FnOnce(&'a Value) -> Option<T>,
+    {
+        match self.0.get(name) {
+            None => Ok(None),
+            Some(value) => match read(value) {
+                Some(typed) => Ok(Some(typed)),
+                None => Err(Error::ClaimType {
+                    name: name.into(),
+                    expected,
+                    found: json_type(value),
+                }
+                .into()),
+            },
+        }
+    }
+}
+
+// the registered claims are read into the payload's own fields, so are left out here to keep the
+// two from disagreeing
+impl<'de> Deserialize<'de> for DynamicClaims {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let mut claims = Map::deserialize(deserializer)?;
+        for name in &REGISTERED_CLAIMS {
+            claims.remove(*name);
+        }
+        Ok(DynamicClaims(claims))
+    }
+}
+
+impl From<Map<String, Value>> for DynamicClaims {
+    fn from(claims: Map<String, Value>) -> DynamicClaims {
+        DynamicClaims(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DynamicClaims;
+    use crate::{Error, Payload, Token};
+    use serde_json::{json, Value};
+
+    const KEY: &[u8] = b"a 32 byte secret key for HS256!!";
+
+    fn claims() -> DynamicClaims {
+        let claims = json!({
+            "iss": "example.com",
+            "email": "alice@example.com",
+            "email_verified": true,
+            "age": 42,
+            "roles": ["admin", "auditor"],
+            "realm_access": { "roles": ["offline_access"] },
+            "https://example.com/tenant": "acme",
+        });
+        serde_json::from_value(claims).unwrap()
+    }
+
+    fn kind(err: anyhow::Error) -> Error {
+        err.downcast::<Error>().unwrap()
+    }
+
+    #[test]
+    fn typed_getters() {
+        let claims = claims();
+
+        assert_eq!(Some("alice@example.com"), claims.get_str("email").unwrap());
+        assert_eq!(Some(true), claims.get_bool("email_verified").unwrap());
+        assert_eq!(Some(42), claims.get_i64("age").unwrap());
+        assert_eq!(Some(42.0), claims.get_f64("age").unwrap());
+        assert_eq!(2, claims.get_array("roles").unwrap().unwrap().len());
+        assert!(claims.get_object("realm_access").unwrap().is_some());
+        assert_eq!(
+            Some("acme"),
+            claims.get_str("https://example.com/tenant").unwrap()
+        );
+        assert_eq!(None, claims.get_str("name").unwrap());
+        // registered claims stay in the payload's own fields
+        assert_eq!(None, claims.get("iss"));
+    }
+
+    #[test]
+    fn wrong_type() {
+        let claims = claims();
+
+        assert_eq!(
+            Error::ClaimType {
+                name: "roles".into(),
+                expected: "a string",
+                found: "an array",
+            },
+            kind(claims.get_str("roles").unwrap_err())
+        );
+        assert_eq!(
+            "The claim \"email\" is a string rather than an array.",
+            claims.get_array("email").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let claims = claims();
+
+        assert_eq!(
+            Some(&json!(["offline_access"])),
+            claims.get_path("realm_access.roles").unwrap()
+        );
+        assert_eq!(Some(&json!(42)), claims.get_path("age").unwrap());
+        assert_eq!(None, claims.get_path("realm_access.groups").unwrap());
+        assert_eq!(None, claims.get_path("resource_access.roles").unwrap());
+        assert_eq!(
+            Error::ClaimType {
+                name: "realm_access.roles".into(),
+                expected: "an object",
+                found: "an array",
+            },
+            kind(claims.get_path("realm_access.roles.admin").unwrap_err())
+        );
+    }
+
+    #[test]
+    fn roundtrip_token() {
+        let token: Token<(), DynamicClaims> = Token::new(
+            Default::default(),
+            Payload {
+                iss: Some("example.com".into()),
+                claims: Some(claims()),
+                ..Payload::default()
+            },
+        );
+        let same: Token<Value, DynamicClaims> = Token::from_raw(&token.sign(KEY).unwrap()).unwrap();
+        let claims = same.payload.claims.as_ref().unwrap();
+
+        assert_eq!(Some("example.com".into()), same.payload.iss);
+        assert_eq!(Some("alice@example.com"), claims.get_str("email").unwrap());
+        assert_eq!(
+            Some(&json!("HS256")),
+            same.header.headers.as_ref().unwrap().get("alg")
+        );
+        assert!(same.verify(KEY).unwrap());
+    }
+}
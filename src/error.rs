@@ -0,0 +1,95 @@
+use crate::header::Algorithm;
+use serde_json::Value;
+use std::fmt;
+
+/// Failures that callers may need to tell apart from the rest. They are returned inside the crate's
+/// `anyhow::Error` and can be recovered with `downcast_ref::<Error>()`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The key is smaller than RFC 7518 allows for the algorithm, both sizes in bits.
+    WeakKey {
+        algorithm: Algorithm,
+        bits: usize,
+        minimum: usize,
+    },
+    /// A NumericDate that is not a finite number of seconds within the dates `chrono` supports.
+    InvalidNumericDate,
+    /// The token's signature or the checks of its claims did not pass.
+    VerificationFailed,
+    /// Custom headers or claims that serialize to something other than a JSON object, so cannot be
+    /// merged with the registered ones. `what` names which and `found` the JSON type they were.
+    NotAnObject {
+        what: &'static str,
+        found: &'static str,
+    },
+    /// Custom headers or claims that give a registered member a different value than its own field,
+    /// which would change what is signed under that name. `what` is `header` or `claim`.
+    Collision { what: &'static str, name: String },
+    /// A claim that is missing or fails a `ClaimRule`, with a description of what the rule expects.
+    InvalidClaim { name: String, expected: String },
+    /// A claim that is present but of another JSON type than the one asked for.
+    ClaimType {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::WeakKey {
+                algorithm,
+                bits,
+                minimum,
+            } => write!(
+                f,
+                "A {} bit key is too weak for {:?}, which needs at least {} bits.",
+                bits, algorithm, minimum
+            ),
+            Error::InvalidNumericDate => write!(
+                f,
+                "NumericDate is not a number of seconds within the supported range of dates."
+            ),
+            Error::VerificationFailed => write!(f, "The token could not be verified."),
+            Error::NotAnObject { what, found } => write!(
+                f,
+                "The {} must serialize to a JSON object, not {}.",
+                what, found
+            ),
+            Error::Collision { what, ref name } => write!(
+                f,
+                "The custom {}s give the registered {} {:?} a different value.",
+                what, what, name
+            ),
+            Error::InvalidClaim {
+                ref name,
+                ref expected,
+            } => write!(f, "The claim {:?} must be {}.", name, expected),
+            Error::ClaimType {
+                ref name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "The claim {:?} is {} rather than {}.",
+                name, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Describes the type of a JSON value for error messages.
+pub(crate) fn json_type(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
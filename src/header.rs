@@ -1,8 +1,14 @@
 use super::Result;
+use crate::{
+    date::NumericDate,
+    error::{json_type, Error},
+};
 use anyhow::format_err;
 use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::{self, Value};
+use serde_json::{self, Map, Value};
+
+pub(crate) const REGISTERED_HEADERS: [&str; 5] = ["alg", "kid", "x5c", "x5t", "x5t#S256"];
 
 /// An extensible Header that provides only algorithm field and allows for additional fields to be
 /// passed in via a struct that can be serialized and deserialized. Unlike the Claims struct, there
@@ -12,12 +18,28 @@ use serde_json::{self, Value};
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Header<T = ()> {
     pub alg: Algorithm,
+    /// Identifies the key the token was signed with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// The certificate chain for the signing key, leaf first, each standard base64 encoded DER.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x5c: Option<Vec<String>>,
+    /// The base64url encoded SHA-1 thumbprint of the leaf certificate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x5t: Option<String>,
+    /// The base64url encoded SHA-256 thumbprint of the leaf certificate.
+    #[serde(rename = "x5t#S256", skip_serializing_if = "Option::is_none")]
+    pub x5t_s256: Option<String>,
     #[serde(skip_serializing)]
     pub headers: Option<T>,
+    /// Members that are neither registered nor additional headers, kept by `from_base64_preserving`
+    /// and written out again when encoding, unless already set by another field.
+    #[serde(skip)]
+    pub unknown: Option<Map<String, Value>>,
 }
 
 /// Supported algorithms, each representing a valid signature and digest combination.
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Algorithm {
     HS256,
     HS384,
@@ -25,61 +47,167 @@ pub enum Algorithm {
     RS256,
     RS384,
     RS512,
+    ES256,
+    ES384,
+    ES512,
+    EdDSA,
 }
 
 impl<T: Serialize + DeserializeOwned> Header<T> {
     /// Decode from base64.
     pub fn from_base64(raw: &str) -> Result<Header<T>> {
         let data = decode_config(raw, URL_SAFE_NO_PAD)?;
-        let own: Header<T> = serde_json::from_slice(&data)?;
+        Header::from_slice(&data)
+    }
 
-        let headers: Option<T> = serde_json::from_slice(&data).ok();
+    /// Decode from base64 as `from_base64` does, also keeping any unknown members so that
+    /// re-encoding the header does not drop them.
+    pub fn from_base64_preserving(raw: &str) -> Result<Header<T>> {
+        let data = decode_config(raw, URL_SAFE_NO_PAD)?;
+        Header::from_slice_preserving(&data)
+    }
 
+    pub(crate) fn from_slice_preserving(data: &[u8]) -> Result<Header<T>> {
+        let header: Header<T> = Header::from_slice(data)?;
+        let unknown = unknown_members(data, &header, header.headers.as_ref())?;
         Ok(Header {
-            alg: own.alg,
-            headers,
+            unknown: Some(unknown),
+            ..header
         })
     }
 
     /// Encode to a string.
     pub fn to_base64(&self) -> Result<String> {
-        if let Value::Object(mut own_map) = serde_json::to_value(&self)? {
-            match self.headers {
-                Some(ref headers) => {
-                    if let Value::Object(extra_map) = serde_json::to_value(&headers)? {
-                        own_map.extend(extra_map);
-                        let s = serde_json::to_string(&own_map)?;
-                        let enc = encode_config((&*s).as_bytes(), URL_SAFE_NO_PAD);
-                        Ok(enc)
-                    } else {
-                        Err(format_err!("Could not access additional headers."))
+        self.to_base64_with_kid(None)
+    }
+
+    /// Encode to a string, with a `kid` decided at signing time in place of any other.
+    pub(crate) fn to_base64_with_kid(&self, kid: Option<&str>) -> Result<String> {
+        let s = self.to_json_with_kid(kid)?;
+        Ok(encode_config(s.as_bytes(), URL_SAFE_NO_PAD))
+    }
+
+    /// The JSON that `to_base64_with_kid` encodes.
+    pub(crate) fn to_json_with_kid(&self, kid: Option<&str>) -> Result<String> {
+        if let Value::Object(mut own_map) = serde_json::to_value(self)? {
+            if let Some(ref headers) = self.headers {
+                match serde_json::to_value(headers)? {
+                    Value::Object(extra_map) => merge_custom(&mut own_map, extra_map, "header")?,
+                    other => {
+                        return Err(Error::NotAnObject {
+                            what: "additional headers",
+                            found: json_type(&other),
+                        }
+                        .into())
                     }
                 }
-                None => {
-                    let s = serde_json::to_string(&own_map)?;
-                    let enc = encode_config((&*s).as_bytes(), URL_SAFE_NO_PAD);
-                    Ok(enc)
+            }
+            if let Some(ref unknown) = self.unknown {
+                for (name, value) in unknown {
+                    own_map.entry(name).or_insert_with(|| value.clone());
                 }
             }
+            if let Some(kid) = kid {
+                own_map.insert("kid".into(), kid.into());
+            }
+            Ok(serde_json::to_string(&own_map)?)
         } else {
             Err(format_err!("Could not access default header."))
         }
     }
 }
 
+impl<'de, T: Deserialize<'de>> Header<T> {
+    /// Decode from JSON already decoded from base64, letting the additional headers borrow from it.
+    pub(crate) fn from_slice(data: &'de [u8]) -> Result<Header<T>> {
+        let own: Header<T> = serde_json::from_slice(data)?;
+
+        let headers: Option<T> = serde_json::from_slice(data).ok();
+
+        Ok(Header { headers, ..own })
+    }
+}
+
 impl<T> Default for Header<T> {
     fn default() -> Header<T> {
         Header {
             alg: Algorithm::HS256,
+            kid: None,
+            x5c: None,
+            x5t: None,
+            x5t_s256: None,
             headers: None,
+            unknown: None,
+        }
+    }
+}
+
+/// Merge custom members into the registered ones. A custom member may fill in a registered one that
+/// is unset or repeat its value, but giving it another value is an `Error::Collision`, so that a
+/// custom type can never change what is signed under a registered name such as `alg` or `exp`.
+/// Numbers repeat a registered value when they are the same `NumericDate`, however written.
+pub(crate) fn merge_custom(
+    own: &mut Map<String, Value>,
+    custom: Map<String, Value>,
+    what: &'static str,
+) -> Result<()> {
+    for (name, value) in custom {
+        match own.get(&name) {
+            Some(registered) if !same_value(registered, &value) => {
+                return Err(Error::Collision { what, name }.into())
+            }
+            Some(_) => {}
+            None => {
+                own.insert(name, value);
+            }
         }
     }
+    Ok(())
+}
+
+// a date claim read as 1.7e9 or 1700000000.0 is written back by `NumericDate` as 1700000000
+fn same_value(registered: &Value, custom: &Value) -> bool {
+    match (
+        NumericDate::deserialize(registered),
+        NumericDate::deserialize(custom),
+    ) {
+        (Ok(registered), Ok(custom)) => registered == custom,
+        _ => registered == custom,
+    }
+}
+
+/// The members of a JSON object that appear in neither the registered fields nor the custom ones,
+/// as each serializes.
+pub(crate) fn unknown_members<R, C>(
+    data: &[u8],
+    registered: &R,
+    custom: Option<&C>,
+) -> Result<Map<String, Value>>
+where
+    R: Serialize,
+    C: Serialize,
+{
+    let mut unknown: Map<String, Value> = serde_json::from_slice(data)?;
+    let mut remove = |value: Value| {
+        if let Value::Object(known) = value {
+            for name in known.keys() {
+                unknown.remove(name);
+            }
+        }
+    };
+    remove(serde_json::to_value(registered)?);
+    if let Some(custom) = custom {
+        remove(serde_json::to_value(custom)?);
+    }
+    Ok(unknown)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Algorithm, Header};
+    use super::{Algorithm, Header, REGISTERED_HEADERS};
+    use crate::Error;
     use serde::{Deserialize, Serialize};
+    use serde_json::{Map, Value};
 
     #[derive(Debug, Serialize, Deserialize, PartialEq)]
     struct CustomHeaders {
@@ -100,6 +228,7 @@ mod tests {
         let enc = "eyJhbGciOiJIUzI1NiIsImtpZCI6IjFLU0YzZyIsInR5cCI6IkpXVCJ9";
         let header: Header<CustomHeaders> = Header::from_base64(enc).unwrap();
 
+        assert_eq!(header.kid, Some("1KSF3g".to_string()));
         let headers = header.headers.unwrap();
         assert_eq!(headers.kid, "1KSF3g".to_string());
         assert_eq!(headers.typ, "JWT".to_string());
@@ -132,19 +261,126 @@ mod tests {
     fn roundtrip() {
         let header: Header<()> = Header::default();
         let enc = header.to_base64().unwrap();
-        assert_eq!(header, Header::from_base64(&*enc).unwrap());
+        assert_eq!(header, Header::from_base64(&enc).unwrap());
+    }
+
+    #[test]
+    fn roundtrip_x5t_s256() {
+        let header: Header<()> = Header {
+            x5t_s256: Some("thumbprint".into()),
+            ..Header::default()
+        };
+        let enc = header.to_base64().unwrap();
+        assert_eq!("eyJhbGciOiJIUzI1NiIsIng1dCNTMjU2IjoidGh1bWJwcmludCJ9", enc);
+        assert_eq!(header, Header::from_base64(&enc).unwrap());
+    }
+
+    #[test]
+    fn roundtrip_kid() {
+        let header: Header<()> = Header {
+            kid: Some("1KSF3g".into()),
+            ..Header::default()
+        };
+        let enc = header.to_base64().unwrap();
+        assert_eq!("eyJhbGciOiJIUzI1NiIsImtpZCI6IjFLU0YzZyJ9", enc);
+        assert_eq!(header, Header::from_base64(&enc).unwrap());
+    }
+
+    #[test]
+    fn kid_at_signing() {
+        let header: Header<()> = Header {
+            kid: Some("old".into()),
+            ..Header::default()
+        };
+        let enc = header.to_base64_with_kid(Some("1KSF3g")).unwrap();
+        assert_eq!("eyJhbGciOiJIUzI1NiIsImtpZCI6IjFLU0YzZyJ9", enc);
+    }
+
+    #[test]
+    fn custom_collisions() {
+        let registered = || Header {
+            kid: Some("1KSF3g".into()),
+            x5c: Some(vec!["MIIB".into()]),
+            x5t: Some("sha1".into()),
+            x5t_s256: Some("sha256".into()),
+            ..Header::default()
+        };
+        let encoded = serde_json::to_value(registered()).unwrap();
+        for &name in &REGISTERED_HEADERS {
+            let member = |value: Value| {
+                let mut custom = Map::new();
+                custom.insert(name.into(), value);
+                Some(custom)
+            };
+            let other = match name {
+                "alg" => Value::from("none"),
+                "x5c" => Value::from(vec!["MIIC"]),
+                _ => Value::from("other"),
+            };
+
+            let same = Header {
+                headers: member(encoded[name].clone()),
+                ..registered()
+            };
+            assert!(same.to_base64().is_ok(), "repeating {}", name);
+
+            let different = Header {
+                headers: member(other),
+                ..registered()
+            };
+            assert_eq!(
+                Some(&Error::Collision {
+                    what: "header",
+                    name: name.into(),
+                }),
+                different.to_base64().unwrap_err().downcast_ref::<Error>(),
+                "changing {}",
+                name
+            );
+        }
+
+        let unset = Header {
+            headers: Some(CustomHeaders {
+                kid: "1KSF3g".into(),
+                typ: "JWT".into(),
+            }),
+            ..Header::default()
+        };
+        let filled: Header<()> = Header::from_base64(&unset.to_base64().unwrap()).unwrap();
+        assert_eq!(Some("1KSF3g".into()), filled.kid);
+    }
+
+    #[test]
+    fn preserve_unknown() {
+        // {"alg":"HS256","kid":"1KSF3g","typ":"JWT","cty":"JWT"}
+        let enc = "eyJhbGciOiJIUzI1NiIsImtpZCI6IjFLU0YzZyIsInR5cCI6IkpXVCIsImN0eSI6IkpXVCJ9";
+        let header: Header<CustomHeaders> = Header::from_base64_preserving(enc).unwrap();
+
+        let mut unknown = Map::new();
+        unknown.insert("cty".into(), "JWT".into());
+        assert_eq!(Some(unknown), header.unknown);
+
+        let same: Header<()> =
+            Header::from_base64_preserving(&header.to_base64().unwrap()).unwrap();
+        let unknown = same.unknown.unwrap();
+        assert_eq!(Some(&"JWT".into()), unknown.get("typ"));
+        assert_eq!(Some(&"JWT".into()), unknown.get("cty"));
+        assert!(!unknown.contains_key("kid"));
     }
 
     #[test]
     fn roundtrip_custom() {
         let header: Header<CustomHeaders> = Header {
             alg: Algorithm::RS512,
+            // a custom kid is also read back as the registered one
+            kid: Some("1KSF3g".into()),
             headers: Some(CustomHeaders {
                 kid: "1KSF3g".into(),
                 typ: "JWT".into(),
             }),
+            ..Header::default()
         };
         let enc = header.to_base64().unwrap();
-        assert_eq!(header, Header::from_base64(&*enc).unwrap());
+        assert_eq!(header, Header::from_base64(&enc).unwrap());
     }
 }
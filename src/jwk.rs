@@ -0,0 +1,235 @@
+use super::Result;
+use crate::{
+    crypt::{self, b64},
+    header::Algorithm,
+};
+use anyhow::format_err;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+use zeroize::Zeroizing;
+
+/// A JSON Web Key, as described in RFC 7517 and RFC 7518. The members for every key type are
+/// present, those not relevant to a key's `kty` are left as `None` and omitted from the JSON.
+/// Binary values, like the RSA modulus or an EC coordinate, are base64url encoded big endian
+/// integers or octet strings. Private members are zeroed when dropped, and `Debug` prints
+/// `[REDACTED]` in place of them.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Jwk {
+    /// The key type, one of `RSA`, `EC`, `OKP` or `oct`.
+    pub kty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alg: Option<Algorithm>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+    /// The curve for `EC` and `OKP` keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    /// The private exponent for `RSA` keys, the private key for `EC` and `OKP` keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub d: Option<Zeroizing<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub p: Option<Zeroizing<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub q: Option<Zeroizing<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dp: Option<Zeroizing<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dq: Option<Zeroizing<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qi: Option<Zeroizing<String>>,
+    /// The secret for `oct` keys.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub k: Option<Zeroizing<String>>,
+}
+
+impl Jwk {
+    /// Whether the key carries private material, a private key or a symmetric secret.
+    pub fn is_private(&self) -> bool {
+        self.d.is_some() || self.k.is_some()
+    }
+
+    /// The RFC 7638 thumbprint of the key, the base64url encoded SHA-256 hash of only its required
+    /// members in a canonical form. Private members play no part, so a private key has the same
+    /// thumbprint as its public half, which makes it a stable choice of `kid`.
+    pub fn thumbprint(&self) -> Result<String> {
+        let required = match self.kty.as_str() {
+            "RSA" => vec![("e", self.e.as_deref()), ("n", self.n.as_deref())],
+            "EC" => vec![
+                ("crv", self.crv.as_deref()),
+                ("x", self.x.as_deref()),
+                ("y", self.y.as_deref()),
+            ],
+            "OKP" => vec![("crv", self.crv.as_deref()), ("x", self.x.as_deref())],
+            "oct" => vec![("k", self.k.as_ref().map(|k| k.as_str()))],
+            kty => return Err(format_err!("Unknown key type {}.", kty)),
+        };
+
+        // a BTreeMap serializes its keys in the lexicographic order the RFC requires
+        let mut members = BTreeMap::new();
+        members.insert("kty", self.kty.as_str());
+        for (name, value) in required {
+            let value = value.ok_or_else(|| {
+                format_err!("The {} member is required for a {} key.", name, self.kty)
+            })?;
+            members.insert(name, value);
+        }
+
+        let json = serde_json::to_string(&members)?;
+        Ok(b64(&crypt::sha256(json.as_bytes())?))
+    }
+}
+
+impl fmt::Debug for Jwk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let redacted = |member: &Option<Zeroizing<String>>| member.as_ref().map(|_| "[REDACTED]");
+        f.debug_struct("Jwk")
+            .field("kty", &self.kty)
+            .field("alg", &self.alg)
+            .field("kid", &self.kid)
+            .field("key_use", &self.key_use)
+            .field("crv", &self.crv)
+            .field("n", &self.n)
+            .field("e", &self.e)
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("d", &redacted(&self.d))
+            .field("p", &redacted(&self.p))
+            .field("q", &redacted(&self.q))
+            .field("dp", &redacted(&self.dp))
+            .field("dq", &redacted(&self.dq))
+            .field("qi", &redacted(&self.qi))
+            .field("k", &redacted(&self.k))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Jwk;
+    use zeroize::Zeroizing;
+
+    fn rfc_7638_rsa() -> Jwk {
+        Jwk {
+            kty: "RSA".into(),
+            n: Some(
+                "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxu\
+                 hDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN\
+                 5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5\
+                 hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBni\
+                 Iqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw"
+                    .into(),
+            ),
+            e: Some("AQAB".into()),
+            ..Jwk::default()
+        }
+    }
+
+    #[test]
+    fn rsa_thumbprint() {
+        assert_eq!(
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs",
+            rfc_7638_rsa().thumbprint().unwrap()
+        );
+    }
+
+    #[test]
+    fn okp_thumbprint() {
+        // from RFC 8037, appendix A.3
+        let jwk = Jwk {
+            kty: "OKP".into(),
+            crv: Some("Ed25519".into()),
+            x: Some("11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo".into()),
+            ..Jwk::default()
+        };
+
+        assert_eq!(
+            "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k",
+            jwk.thumbprint().unwrap()
+        );
+    }
+
+    #[test]
+    fn optional_members_ignored() {
+        let jwk = Jwk {
+            kid: Some("2011-04-29".into()),
+            key_use: Some("sig".into()),
+            d: Some(Zeroizing::new("private".into())),
+            ..rfc_7638_rsa()
+        };
+
+        assert_eq!(
+            rfc_7638_rsa().thumbprint().unwrap(),
+            jwk.thumbprint().unwrap()
+        );
+    }
+
+    #[test]
+    fn ec_thumbprint() {
+        // the key from RFC 7515, appendix A.3, hashed as
+        // {"crv":"P-256","kty":"EC","x":"f83O...","y":"x_FE..."}
+        let jwk = Jwk {
+            kty: "EC".into(),
+            crv: Some("P-256".into()),
+            x: Some("f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU".into()),
+            y: Some("x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0".into()),
+            ..Jwk::default()
+        };
+
+        assert_eq!(
+            "oKIywvGUpTVTyxMQ3bwIIeQUudfr_CkLMjCE19ECD-U",
+            jwk.thumbprint().unwrap()
+        );
+    }
+
+    #[test]
+    fn oct_thumbprint() {
+        // the key from RFC 7517, appendix A.3, hashed as {"k":"Gawg...","kty":"oct"}
+        let jwk = Jwk {
+            kty: "oct".into(),
+            k: Some(Zeroizing::new("GawgguFyGrWKav7AX4VKUg".into())),
+            ..Jwk::default()
+        };
+
+        assert_eq!(
+            "k1JnWRfC-5zzmL72vXIuBgTLfVROXBakS4OmGcrMCoc",
+            jwk.thumbprint().unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_member() {
+        let jwk = Jwk {
+            kty: "EC".into(),
+            crv: Some("P-256".into()),
+            x: Some("f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU".into()),
+            ..Jwk::default()
+        };
+
+        assert!(jwk.thumbprint().is_err());
+        assert!(Jwk::default().thumbprint().is_err());
+    }
+
+    #[test]
+    fn redacted_debug() {
+        let jwk = Jwk {
+            d: Some(Zeroizing::new("private".into())),
+            ..rfc_7638_rsa()
+        };
+        let debug = format!("{:?}", jwk);
+
+        assert!(!debug.contains("private"));
+        assert!(debug.contains("d: Some(\"[REDACTED]\")"));
+        assert!(debug.contains("e: Some(\"AQAB\")"));
+        assert!(debug.contains("k: None"));
+    }
+}
@@ -0,0 +1,479 @@
+use super::Result;
+use crate::{
+    crypt, header::Algorithm, secret::SecretKey, ClaimsValidator, Payload, SignOptions, Token,
+    VerifyOptions,
+};
+use anyhow::format_err;
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A key tokens may be verified with, along with the window of time in which it is trusted. Either
+/// end of the window may be left open. A `KeyRing` checks the window against the token's `iat`,
+/// which is written by whoever signed the token, so it does not protect against a retired key
+/// that has leaked: its holder can backdate `iat` into the window. Remove such a key from the ring
+/// instead.
+#[derive(Debug, Clone)]
+pub struct VerificationKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    /// The public key or, for HMAC, the shared secret.
+    pub key: SecretKey,
+    pub not_before: Option<DateTime<Utc>>,
+    pub not_after: Option<DateTime<Utc>>,
+}
+
+impl VerificationKey {
+    /// A key trusted at any time.
+    pub fn new(kid: &str, algorithm: Algorithm, key: &[u8]) -> VerificationKey {
+        VerificationKey {
+            kid: kid.into(),
+            algorithm,
+            key: key.into(),
+            not_before: None,
+            not_after: None,
+        }
+    }
+
+    /// Whether the key is trusted at the time, the window including both of its ends.
+    pub fn is_valid_at(&self, time: DateTime<Utc>) -> bool {
+        let started = match self.not_before {
+            Some(not_before) => not_before <= time,
+            None => true,
+        };
+        let ended = match self.not_after {
+            Some(not_after) => not_after < time,
+            None => false,
+        };
+        started && !ended
+    }
+}
+
+#[derive(Debug)]
+struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    key: SecretKey,
+}
+
+/// The keys for a rollover: one current key that new tokens are signed with and every key that
+/// tokens may still be verified with, each identified by a `kid`.
+#[derive(Debug)]
+pub struct KeyRing {
+    current: SigningKey,
+    verification: Vec<VerificationKey>,
+}
+
+impl KeyRing {
+    /// Start a ring whose current key signs with the private or secret key and verifies with the
+    /// public key or, for HMAC, the same secret.
+    pub fn new(
+        kid: &str,
+        algorithm: Algorithm,
+        signing_key: &[u8],
+        verification_key: &[u8],
+    ) -> KeyRing {
+        KeyRing {
+            current: SigningKey {
+                kid: kid.into(),
+                algorithm,
+                key: signing_key.into(),
+            },
+            verification: vec![VerificationKey::new(kid, algorithm, verification_key)],
+        }
+    }
+
+    /// Make a new key current for signing, keeping every earlier key for verification. Retire an
+    /// earlier key by setting its `not_after` through `verification_key_mut`. A `kid` already in
+    /// the ring is an error, leaving the current key as it was.
+    pub fn rotate(
+        &mut self,
+        kid: &str,
+        algorithm: Algorithm,
+        signing_key: &[u8],
+        verification_key: &[u8],
+    ) -> Result<()> {
+        self.add_verification_key(VerificationKey::new(kid, algorithm, verification_key))?;
+        self.current = SigningKey {
+            kid: kid.into(),
+            algorithm,
+            key: signing_key.into(),
+        };
+        Ok(())
+    }
+
+    /// Trust another key for verification only, such as one that will become current later. A
+    /// `kid` already in the ring is an error.
+    pub fn add_verification_key(&mut self, key: VerificationKey) -> Result<()> {
+        if self.verification.iter().any(|known| known.kid == key.kid) {
+            return Err(format_err!(
+                "A key with the kid {} is already in the ring.",
+                key.kid
+            ));
+        }
+        self.verification.push(key);
+        Ok(())
+    }
+
+    pub fn verification_key_mut(&mut self, kid: &str) -> Option<&mut VerificationKey> {
+        self.verification.iter_mut().find(|key| key.kid == kid)
+    }
+
+    pub fn verification_keys(&self) -> &[VerificationKey] {
+        &self.verification
+    }
+
+    /// The `kid` of the key new tokens are signed with.
+    pub fn current_kid(&self) -> &str {
+        &self.current.kid
+    }
+
+    /// Sign the token with the current key, stamping its `kid` into the signed header. The current
+    /// key's algorithm must match the one in the token's header.
+    pub fn sign<H, C>(&self, token: &Token<H, C>) -> Result<String>
+    where
+        H: Serialize + DeserializeOwned,
+        C: Serialize + DeserializeOwned,
+    {
+        self.sign_with_options(token, &SignOptions::default())
+    }
+
+    /// Sign as `sign` does, adjusting what is signed with the options. The current key's `kid`
+    /// always takes the place of a thumbprint.
+    pub fn sign_with_options<H, C>(
+        &self,
+        token: &Token<H, C>,
+        options: &SignOptions,
+    ) -> Result<String>
+    where
+        H: Serialize + DeserializeOwned,
+        C: Serialize + DeserializeOwned,
+    {
+        if self.current.algorithm != token.header.alg {
+            return Err(format_err!(
+                "The current key uses {:?} but the header specifies {:?}.",
+                self.current.algorithm,
+                token.header.alg
+            ));
+        }
+        token.sign_stamped(&self.current.key, Some(&self.current.kid), options)
+    }
+
+    /// Verify a parsed token with any key valid at the time it was issued, taken from its `iat` or,
+    /// without one, the current time. When the token names a `kid` only that key is tried,
+    /// otherwise every key for the header's algorithm is, passing over any that cannot be read or
+    /// did not make the signature. A key below the RFC 7518 minimums is an `Error::WeakKey`, and
+    /// errors from the revocation store or replay cache are returned as they are.
+    ///
+    /// The `iat` is written by whoever signed the token, so the holder of a retired key can
+    /// backdate it into the key's window. `UnverifiedToken::verify_with_key_ring` returns the token
+    /// only once it passes.
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub fn verify<H, C>(&self, token: &Token<H, C>) -> Result<bool>
+    where
+        H: Serialize + DeserializeOwned,
+        C: Serialize + DeserializeOwned,
+    {
+        self.verify_with_options(token, &VerifyOptions::default())
+    }
+
+    /// Verify as `verify` does, adjusting the checks with the options. The options' clock stands in
+    /// for the current time.
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub fn verify_with_options<H, C>(
+        &self,
+        token: &Token<H, C>,
+        options: &VerifyOptions,
+    ) -> Result<bool>
+    where
+        H: Serialize + DeserializeOwned,
+        C: Serialize + DeserializeOwned,
+    {
+        self.verify_checked(token, options, &|_| Ok(()))
+    }
+
+    /// Verify as `verify_with_options` does, then check the claims with the validator as
+    /// `Token::verify_with_validator` does.
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub fn verify_with_validator<H, C, V>(
+        &self,
+        token: &Token<H, C>,
+        options: &VerifyOptions,
+        validator: &V,
+    ) -> Result<bool>
+    where
+        H: Serialize + DeserializeOwned,
+        C: Serialize + DeserializeOwned,
+        V: ClaimsValidator<C> + ?Sized,
+    {
+        self.verify_checked(token, options, &|payload| validator.validate(payload))
+    }
+
+    fn verify_checked<H, C>(
+        &self,
+        token: &Token<H, C>,
+        options: &VerifyOptions,
+        validate: &dyn Fn(&Payload<C>) -> Result<()>,
+    ) -> Result<bool> {
+        let (sig, data) = match token.signed_parts() {
+            Some(parts) => parts,
+            None => return Ok(false),
+        };
+        if !token
+            .payload
+            .verify_with_leeway(&*options.clock, options.leeway)
+        {
+            return Ok(false);
+        }
+        let issued = match token.payload.iat {
+            Some(iat) => iat.to_datetime(),
+            None => options.clock.now(),
+        };
+
+        let alg = &token.header.alg;
+        for key in &self.verification {
+            let named = match token.header.kid {
+                Some(ref kid) => *kid == key.kid,
+                None => true,
+            };
+            if !(named && key.algorithm == *alg && key.is_valid_at(issued)) {
+                continue;
+            }
+            if !options.allow_weak_keys {
+                crypt::check_key_strength(&key.key, alg)?;
+            }
+            // only the signature check decides between keys, whatever follows is about the token
+            if crypt::verify(sig, data, &key.key, alg).unwrap_or(false) {
+                return token.accept_signed(options, validate);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyRing, VerificationKey};
+    use crate::{
+        keys, Algorithm::*, ClaimRule, Error, Header, MemoryReplayCache, Payload, SignOptions,
+        Token, VerifyOptions,
+    };
+    use chrono::{Duration, Utc};
+    use std::sync::Arc;
+
+    fn ring() -> (KeyRing, Vec<u8>) {
+        let old = keys::generate(HS256).unwrap();
+        let ring = KeyRing::new("old", HS256, old.signing_key(), old.verification_key());
+        (ring, old.signing_key().to_vec())
+    }
+
+    fn issued(minutes_ago: i64) -> Token {
+        Token::new(
+            Header::default(),
+            Payload {
+                iat: Some((Utc::now() - Duration::minutes(minutes_ago)).into()),
+                ..Payload::default()
+            },
+        )
+    }
+
+    #[test]
+    fn sign_stamps_kid() {
+        let (ring, _) = ring();
+        let raw = ring.sign(&issued(0)).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+
+        assert_eq!(Some("old".to_string()), same.header.kid);
+        assert!(ring.verify(&same).unwrap());
+    }
+
+    #[test]
+    fn verify_across_rotation() {
+        let (mut ring, _) = ring();
+        let before = Token::<()>::from_raw(&ring.sign(&issued(0)).unwrap()).unwrap();
+
+        let new = keys::generate(HS256).unwrap();
+        ring.rotate("new", HS256, new.signing_key(), new.verification_key())
+            .unwrap();
+        let after: Token = Token::from_raw(&ring.sign(&issued(0)).unwrap()).unwrap();
+
+        assert_eq!("new", ring.current_kid());
+        assert_eq!(Some("new".to_string()), after.header.kid);
+        assert!(ring.verify(&before).unwrap());
+        assert!(ring.verify(&after).unwrap());
+    }
+
+    #[test]
+    fn retired_key() {
+        let (mut ring, _) = ring();
+        let earlier: Token = Token::from_raw(&ring.sign(&issued(60)).unwrap()).unwrap();
+        let later: Token = Token::from_raw(&ring.sign(&issued(1)).unwrap()).unwrap();
+
+        let new = keys::generate(HS256).unwrap();
+        ring.rotate("new", HS256, new.signing_key(), new.verification_key())
+            .unwrap();
+        ring.verification_key_mut("old").unwrap().not_after =
+            Some(Utc::now() - Duration::minutes(30));
+
+        assert!(ring.verify(&earlier).unwrap());
+        assert!(!ring.verify(&later).unwrap());
+    }
+
+    #[test]
+    fn duplicate_kid() {
+        let (mut ring, _) = ring();
+        let new = keys::generate(HS256).unwrap();
+
+        assert!(ring
+            .rotate("old", HS256, new.signing_key(), new.verification_key())
+            .is_err());
+        assert!(ring
+            .add_verification_key(VerificationKey::new("old", HS256, new.verification_key()))
+            .is_err());
+        assert_eq!("old", ring.current_kid());
+        assert_eq!(1, ring.verification_keys().len());
+    }
+
+    #[test]
+    fn other_keys_passed_over() {
+        let (ring, key) = ring();
+        let other = keys::generate(HS256).unwrap();
+        let mut other_first = KeyRing::new(
+            "other",
+            HS256,
+            other.signing_key(),
+            other.verification_key(),
+        );
+        other_first
+            .add_verification_key(ring.verification_keys()[0].clone())
+            .unwrap();
+        let raw = issued(0).sign(&key).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+
+        assert!(other_first.verify(&same).unwrap());
+    }
+
+    #[test]
+    fn weak_key() {
+        let (ring, key) = ring();
+        let mut weak_first = KeyRing::new("weak", HS256, b"short", b"short");
+        weak_first
+            .add_verification_key(ring.verification_keys()[0].clone())
+            .unwrap();
+        let raw = issued(0).sign(&key).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+        let err = weak_first.verify(&same).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::WeakKey { .. })
+        ));
+    }
+
+    #[test]
+    fn full_replay_cache() {
+        let (ring, _) = ring();
+        let token = Token {
+            payload: Payload {
+                jti: Some("a".into()),
+                ..issued(0).payload
+            },
+            ..issued(0)
+        };
+        let same: Token = Token::from_raw(&ring.sign(&token).unwrap()).unwrap();
+        let options = VerifyOptions {
+            replay_cache: Some(Arc::new(MemoryReplayCache::new(0))),
+            ..VerifyOptions::default()
+        };
+
+        assert!(ring.verify_with_options(&same, &options).is_err());
+    }
+
+    #[test]
+    fn validator() {
+        let (ring, _) = ring();
+        let token = Token {
+            payload: Payload {
+                sub: Some("alice".into()),
+                ..issued(0).payload
+            },
+            ..issued(0)
+        };
+        let same: Token = Token::from_raw(&ring.sign(&token).unwrap()).unwrap();
+        let options = VerifyOptions::default();
+
+        assert!(ring
+            .verify_with_validator(&same, &options, &ClaimRule::equals("sub", "alice"))
+            .unwrap());
+        let err = ring
+            .verify_with_validator(&same, &options, &ClaimRule::equals("sub", "bob"))
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidClaim { .. })
+        ));
+    }
+
+    #[test]
+    fn key_not_yet_valid() {
+        let (mut ring, _) = ring();
+        let next = keys::generate(HS256).unwrap();
+        ring.add_verification_key(VerificationKey {
+            not_before: Some(Utc::now() + Duration::minutes(30)),
+            ..VerificationKey::new("next", HS256, next.verification_key())
+        })
+        .unwrap();
+
+        let token = Token {
+            header: Header {
+                kid: Some("next".into()),
+                ..Header::default()
+            },
+            ..issued(0)
+        };
+        let raw = token.sign(next.signing_key()).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+
+        assert_eq!(2, ring.verification_keys().len());
+        assert!(!ring.verify(&same).unwrap());
+    }
+
+    #[test]
+    fn without_kid() {
+        let (ring, key) = ring();
+        let raw = issued(0).sign(&key).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+
+        assert!(ring.verify(&same).unwrap());
+    }
+
+    #[test]
+    fn unknown_kid_and_algorithm() {
+        let (ring, key) = ring();
+        let token = Token {
+            header: Header {
+                kid: Some("other".into()),
+                ..Header::default()
+            },
+            ..issued(0)
+        };
+        let raw = token.sign(&key).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+        assert!(!ring.verify(&same).unwrap());
+
+        let token = Token {
+            header: Header {
+                alg: HS512,
+                ..Header::default()
+            },
+            ..issued(0)
+        };
+        assert!(ring.sign(&token).is_err());
+        let options = SignOptions {
+            allow_weak_keys: true,
+            ..SignOptions::default()
+        };
+        let raw = token.sign_with_options(&key, &options).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+        assert!(!ring.verify(&same).unwrap());
+    }
+}
@@ -0,0 +1,289 @@
+//! Working with the keys tokens are signed and verified with.
+//!
+//! Wherever a key is accepted it may be PEM or DER encoded. Private keys may be PKCS#1 RSA keys,
+//! SEC1 EC keys or PKCS#8 keys of any type, public keys may be SubjectPublicKeyInfo structures,
+//! PKCS#1 RSA public keys or X.509 certificates, whose public key is used. HMAC secrets are the raw
+//! bytes of the secret.
+use super::Result;
+use crate::{
+    crypt::{self, Backend, DefaultBackend},
+    header::Algorithm,
+    jwk::Jwk,
+    secret::SecretKey,
+};
+use anyhow::format_err;
+use base64::encode;
+use zeroize::Zeroizing;
+
+/// A newly generated key, the private half held as unencrypted PKCS#8 and the public half as a
+/// SubjectPublicKeyInfo, both DER encoded. An HMAC key is a single random secret as long as the
+/// algorithm's hash output, which serves as both halves and has no DER or PEM form. It is only
+/// held once, as a `SecretKey`, so `Debug` never prints it.
+#[derive(Debug)]
+pub struct KeyPair {
+    algorithm: Algorithm,
+    private: SecretKey,
+    // None for an HMAC secret
+    public: Option<Vec<u8>>,
+}
+
+/// Generate a new key suitable for the algorithm: a random HMAC secret as long as the hash, a
+/// 2048 bit RSA key, an EC key on the algorithm's curve or an Ed25519 key.
+pub fn generate(algorithm: Algorithm) -> Result<KeyPair> {
+    let (private, public) = crypt::generate(&algorithm)?;
+    Ok(KeyPair {
+        algorithm,
+        private,
+        public,
+    })
+}
+
+impl KeyPair {
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The key to sign tokens with, as accepted by `Token::sign`.
+    pub fn signing_key(&self) -> &[u8] {
+        &self.private
+    }
+
+    /// The key to verify tokens with, as accepted by `Token::verify`.
+    pub fn verification_key(&self) -> &[u8] {
+        match self.public {
+            Some(ref public) => public,
+            None => &self.private,
+        }
+    }
+
+    pub fn private_key_der(&self) -> Result<SecretKey> {
+        self.check_asymmetric()?;
+        Ok(self.private.clone())
+    }
+
+    /// The private key as PEM text, kept as a `SecretKey` so that it is wiped once dropped.
+    pub fn private_key_pem(&self) -> Result<SecretKey> {
+        self.check_asymmetric()?;
+        Ok(pem("PRIVATE KEY", &self.private).as_bytes().into())
+    }
+
+    pub fn public_key_der(&self) -> Result<Vec<u8>> {
+        Ok(self.public()?.to_vec())
+    }
+
+    pub fn public_key_pem(&self) -> Result<String> {
+        Ok(pem("PUBLIC KEY", self.public()?).to_string())
+    }
+
+    /// The whole key as a JWK, including the private members or the HMAC secret.
+    pub fn private_jwk(&self) -> Result<Jwk> {
+        crypt::jwk(&self.private, &self.algorithm, true)
+    }
+
+    /// Only the public members of the key as a JWK, an error for HMAC secrets.
+    pub fn public_jwk(&self) -> Result<Jwk> {
+        crypt::jwk(self.verification_key(), &self.algorithm, false)
+    }
+
+    /// The RFC 7638 thumbprint of the key, the same for both halves.
+    pub fn thumbprint(&self) -> Result<String> {
+        thumbprint(&self.private, self.algorithm)
+    }
+
+    fn check_asymmetric(&self) -> Result<()> {
+        self.public().map(|_| ())
+    }
+
+    fn public(&self) -> Result<&[u8]> {
+        match self.public {
+            Some(ref public) => Ok(public),
+            None => Err(format_err!("HMAC secrets have no DER or PEM form.")),
+        }
+    }
+}
+
+/// Wraps DER in PEM, sized up front so that no partial copy of a private key is left behind by
+/// the string growing.
+fn pem(label: &str, der: &[u8]) -> Zeroizing<String> {
+    let encoded = Zeroizing::new(encode(der));
+    let lines = encoded.len() / 64 + 1;
+    let mut pem = Zeroizing::new(String::with_capacity(
+        encoded.len() + lines + 2 * label.len() + 32,
+    ));
+    pem.push_str(&format!("-----BEGIN {}-----\n", label));
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+/// The RFC 7638 thumbprint of a key for the algorithm, which may be a private key, the public key
+/// of an asymmetric pair or an HMAC secret.
+pub fn thumbprint(key: &[u8], algorithm: Algorithm) -> Result<String> {
+    let secret = crypt::hmac_len(&algorithm).is_some();
+    crypt::jwk(key, &algorithm, secret)?.thumbprint()
+}
+
+/// Decrypt a password protected private key into unencrypted PKCS#8 DER, which can then be given
+/// to `Token::sign`. Encrypted PKCS#8 keys are accepted as PEM or DER and, with the `openssl`
+/// feature, so are traditional encrypted PEM keys.
+pub fn decrypt_private_key(key: &[u8], password: &[u8]) -> Result<SecretKey> {
+    DefaultBackend::decrypt_private_key(key, password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, thumbprint};
+    use crate::{Algorithm::*, DefaultToken, Header};
+
+    #[test]
+    fn generated_keys_sign_and_verify() {
+        for &algorithm in &[HS256, HS384, HS512, RS256, ES256, ES384, ES512, EdDSA] {
+            let pair = generate(algorithm).unwrap();
+            let token: DefaultToken<()> = DefaultToken::new(
+                Header {
+                    alg: algorithm,
+                    ..Header::default()
+                },
+                Default::default(),
+            );
+
+            let raw = token.sign(pair.signing_key()).unwrap();
+            let same = DefaultToken::<()>::from_raw(&raw).unwrap();
+            assert!(same.verify(pair.verification_key()).unwrap());
+
+            if algorithm != HS256 && algorithm != HS384 && algorithm != HS512 {
+                let raw = token.sign(&pair.private_key_pem().unwrap()).unwrap();
+                let same = DefaultToken::<()>::from_raw(&raw).unwrap();
+                assert!(same
+                    .verify(pair.public_key_pem().unwrap().as_bytes())
+                    .unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn hmac_secret_left_out_of_debug() {
+        let debug = format!("{:?}", generate(HS256).unwrap());
+
+        assert!(debug.contains("private: [REDACTED]"));
+        assert!(debug.contains("public: None"));
+    }
+
+    #[test]
+    fn hmac_secrets() {
+        let one = generate(HS384).unwrap();
+        let other = generate(HS384).unwrap();
+
+        assert_eq!(48, one.signing_key().len());
+        assert_ne!(one.signing_key(), other.signing_key());
+        assert_eq!(one.signing_key(), one.verification_key());
+        assert!(one.private_key_pem().is_err());
+        assert!(one.public_jwk().is_err());
+
+        let jwk = one.private_jwk().unwrap();
+        assert_eq!("oct", jwk.kty);
+        assert_eq!(Some(HS384), jwk.alg);
+        assert_eq!(64, jwk.k.unwrap().len());
+    }
+
+    #[test]
+    fn rsa_jwk() {
+        let pair = generate(RS256).unwrap();
+        let private = pair.private_jwk().unwrap();
+        let public = pair.public_jwk().unwrap();
+
+        assert_eq!("RSA", private.kty);
+        assert_eq!(Some("AQAB".to_string()), private.e);
+        assert_eq!(342, private.n.as_ref().unwrap().len());
+        for member in &[
+            &private.d,
+            &private.p,
+            &private.q,
+            &private.dp,
+            &private.dq,
+            &private.qi,
+        ] {
+            assert!(member.is_some());
+        }
+        assert!(private.is_private());
+        assert!(!public.is_private());
+        assert_eq!(private.n, public.n);
+    }
+
+    #[test]
+    fn ec_jwk() {
+        for &(algorithm, crv, width) in &[
+            (ES256, "P-256", 43),
+            (ES384, "P-384", 64),
+            (ES512, "P-521", 88),
+        ] {
+            let private = generate(algorithm).unwrap().private_jwk().unwrap();
+
+            assert_eq!("EC", private.kty);
+            assert_eq!(Some(crv.to_string()), private.crv);
+            assert_eq!(width, private.x.unwrap().len());
+            assert_eq!(width, private.y.unwrap().len());
+            assert_eq!(width, private.d.unwrap().len());
+        }
+    }
+
+    #[test]
+    fn ed25519_jwk() {
+        let pair = generate(EdDSA).unwrap();
+        let private = pair.private_jwk().unwrap();
+        let public = pair.public_jwk().unwrap();
+
+        assert_eq!("OKP", public.kty);
+        assert_eq!(Some("Ed25519".to_string()), public.crv);
+        assert_eq!(43, public.x.as_ref().unwrap().len());
+        assert_eq!(None, public.d);
+        assert_eq!(public.x, private.x);
+        assert_eq!(43, private.d.unwrap().len());
+    }
+
+    #[test]
+    fn thumbprints() {
+        for &algorithm in &[HS256, RS256, ES384, EdDSA] {
+            let pair = generate(algorithm).unwrap();
+            let expected = pair.private_jwk().unwrap().thumbprint().unwrap();
+
+            assert_eq!(expected, pair.thumbprint().unwrap());
+            assert_eq!(
+                expected,
+                thumbprint(pair.verification_key(), algorithm).unwrap()
+            );
+            assert_ne!(expected, generate(algorithm).unwrap().thumbprint().unwrap());
+        }
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    fn sign_with_decrypted_key() {
+        use super::decrypt_private_key;
+        use openssl::{rsa::Rsa, symm::Cipher};
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let encrypted = rsa
+            .private_key_to_pem_passphrase(Cipher::aes_256_cbc(), b"password")
+            .unwrap();
+        let token: DefaultToken<()> = DefaultToken::new(
+            Header {
+                alg: RS256,
+                ..Header::default()
+            },
+            Default::default(),
+        );
+
+        assert!(token.sign(&encrypted).is_err());
+        assert!(decrypt_private_key(&encrypted, b"wrong").is_err());
+
+        let key = decrypt_private_key(&encrypted, b"password").unwrap();
+        let raw = token.sign(&key).unwrap();
+        let same = DefaultToken::<()>::from_raw(&raw).unwrap();
+
+        assert!(same.verify(&rsa.public_key_to_pem().unwrap()).unwrap());
+    }
+}
@@ -1,18 +1,66 @@
 #![crate_name = "medallion"]
 #![crate_type = "lib"]
 #![doc(html_root_url = "https://commandline.github.io/medallion/")]
-///! A crate for working with JSON `WebTokens` that use OpenSSL for RSA signing and encryption and
-///! `serde` and `serde_json` for JSON encoding and decoding.
-///!
-///! Tries to support the standard uses for JWTs while providing reasonable ways to extend,
-///! primarily by adding custom headers and claims to tokens.
+//! A crate for working with JSON `WebTokens` that use OpenSSL for RSA signing and encryption and
+//! `serde` and `serde_json` for JSON encoding and decoding.
+//!
+//! Tries to support the standard uses for JWTs while providing reasonable ways to extend,
+//! primarily by adding custom headers and claims to tokens.
+use anyhow::format_err;
+use base64::{encode_config, URL_SAFE_NO_PAD};
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use date::NumericDate;
+pub use dynamic::DynamicClaims;
+pub use error::Error;
 pub use header::{Algorithm, Header};
-pub use payload::{DefaultPayload, Payload};
+pub use jwk::Jwk;
+pub use key_ring::{KeyRing, VerificationKey};
+pub use keys::KeyPair;
+pub use options::{SignOptions, VerifyOptions};
+pub use payload::{DefaultPayload, Payload, PayloadBuilder, Scopes};
+pub use replay::{MemoryReplayCache, ReplayCache};
+#[cfg(feature = "async")]
+pub use resolver::{KeyResolver, ResolvedKey};
+pub use revocation::{FileRevocationStore, MemoryRevocationStore, RevocationStore};
+pub use secret::SecretKey;
+use segments::Segments;
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+#[cfg(feature = "async")]
+pub use signer::AsyncSigner;
+pub use signer::{LocalSigner, Signer};
+use std::fmt;
+pub use token_ref::{DecodeBuffers, TokenRef};
+pub use unverified::UnverifiedToken;
+pub use validation::{ClaimRule, ClaimsValidator};
+pub use verified::Verified;
+#[cfg(feature = "openssl")]
+pub use x5c::TrustAnchors;
 
+mod clock;
 mod crypt;
+mod date;
+mod dynamic;
+mod error;
 mod header;
+mod jwk;
+mod key_ring;
+pub mod keys;
+mod options;
 mod payload;
+mod replay;
+#[cfg(feature = "async")]
+mod resolver;
+mod revocation;
+mod secret;
+mod segments;
+mod signer;
+mod token_ref;
+mod unverified;
+mod validation;
+mod verified;
+#[cfg(feature = "openssl")]
+mod x5c;
 
 pub use anyhow::Result;
 
@@ -22,11 +70,14 @@ pub use anyhow::Result;
 pub type DefaultToken<H> = Token<H, ()>;
 
 /// Main struct representing a JSON Web Token, composed of a header and a set of claims.
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct Token<H = (), C = ()> {
-    raw: Option<String>,
+    segments: Option<Segments>,
     pub header: Header<H>,
     pub payload: Payload<C>,
+    /// Print `[REDACTED]` in place of the raw token in `Debug` output, so that logging a parsed
+    /// token does not leave a usable bearer token in the logs.
+    pub redact_raw: bool,
 }
 
 /// Provide the ability to parse a token, verify it and sign/serialize it.
@@ -37,47 +88,433 @@ where
 {
     pub fn new(header: Header<H>, payload: Payload<C>) -> Token<H, C> {
         Token {
-            raw: None,
+            segments: None,
             header,
             payload,
+            redact_raw: false,
         }
     }
 
-    /// Parse a token from a string.
+    /// Parse a token from a string, leaving it to the caller to verify it.
+    #[deprecated(
+        note = "use `UnverifiedToken::parse`, which cannot be mistaken for a verified token"
+    )]
     pub fn parse(raw: &str) -> Result<Token<H, C>> {
-        let pieces: Vec<_> = raw.split('.').collect();
+        Self::from_raw(raw)
+    }
+
+    pub(crate) fn from_raw(raw: &str) -> Result<Token<H, C>> {
+        let segments = Segments::parse(raw)?;
+
+        Ok(Token {
+            header: Header::from_slice(segments.header())?,
+            payload: Payload::from_slice(segments.payload())?,
+            segments: Some(segments),
+            redact_raw: false,
+        })
+    }
+
+    /// Parse a token as `parse` does, also keeping the header members and claims that are neither
+    /// registered nor custom, so that re-signing the token does not drop them.
+    pub fn parse_preserving(raw: &str) -> Result<Token<H, C>> {
+        let segments = Segments::parse(raw)?;
 
         Ok(Token {
-            raw: Some(raw.to_owned()),
-            header: Header::from_base64(pieces[0])?,
-            payload: Payload::from_base64(pieces[1])?,
+            header: Header::from_slice_preserving(segments.header())?,
+            payload: Payload::from_slice_preserving(segments.payload())?,
+            segments: Some(segments),
+            redact_raw: false,
         })
     }
 
-    /// Verify a token with a key and the token's specific algorithm.
+    /// Verify a token with a key and the token's specific algorithm. A key below the RFC 7518
+    /// minimums for the algorithm is an `Error::WeakKey`. `UnverifiedToken::verify` returns the
+    /// token only once it passes, which leaves no `bool` to forget to check.
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
     pub fn verify(&self, key: &[u8]) -> Result<bool> {
-        let raw = match self.raw {
-            Some(ref s) => s,
+        self.verify_with_options(key, &VerifyOptions::default())
+    }
+
+    /// Verify a token as `verify` does, adjusting the checks with the options. See
+    /// `UnverifiedToken::verify_with_options`.
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub fn verify_with_options(&self, key: &[u8], options: &VerifyOptions) -> Result<bool> {
+        let (sig, data) = match self.signed_parts() {
+            Some(parts) => parts,
             None => return Ok(false),
         };
+        self.verify_signed(sig, data, key, options, &|_| Ok(()))
+    }
 
-        let pieces: Vec<_> = raw.rsplitn(2, '.').collect();
-        let sig = pieces[0];
-        let data = pieces[1];
+    /// Verify a token as `verify_with_options` does, then check its claims with the validator. The
+    /// validator's error is returned as it is, rather than as a failed verification, so that the
+    /// reason can be told to the caller. It runs before the revocation and replay checks, so that a
+    /// rejected token does not use up its `jti`. See `UnverifiedToken::verify_with_validator`.
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub fn verify_with_validator<V>(
+        &self,
+        key: &[u8],
+        options: &VerifyOptions,
+        validator: &V,
+    ) -> Result<bool>
+    where
+        V: ClaimsValidator<C> + ?Sized,
+    {
+        let (sig, data) = match self.signed_parts() {
+            Some(parts) => parts,
+            None => return Ok(false),
+        };
+        self.verify_signed(sig, data, key, options, &|payload| {
+            validator.validate(payload)
+        })
+    }
 
-        Ok(self.payload.verify() && crypt::verify(sig, data, key, &self.header.alg)?)
+    /// Verify a token with the public key of the leaf certificate in its `x5c` header, once the
+    /// certificate chain has been validated against the trust anchors. An invalid chain, a
+    /// thumbprint that does not match the leaf or an `alg` that is not an asymmetric algorithm for
+    /// the leaf's key is an error rather than a failed verification. See
+    /// `UnverifiedToken::verify_x5c`.
+    #[cfg(feature = "openssl")]
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub fn verify_x5c(&self, anchors: &TrustAnchors) -> Result<bool> {
+        self.verify_x5c_with_options(anchors, &VerifyOptions::default())
+    }
+
+    /// Verify a token as `verify_x5c` does, adjusting the checks with the options. The certificates
+    /// are checked against the options' clock rather than the system time.
+    #[cfg(feature = "openssl")]
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub fn verify_x5c_with_options(
+        &self,
+        anchors: &TrustAnchors,
+        options: &VerifyOptions,
+    ) -> Result<bool> {
+        if self.signed_parts().is_none() {
+            return Ok(false);
+        }
+        let key = x5c::verification_key(&self.header, anchors, options.clock.now())?;
+        self.verify_with_options(&key, options)
     }
 
     /// Generate the signed token from a key with the specific algorithm as a url-safe, base64
-    /// string.
+    /// string. A key below the RFC 7518 minimums for the algorithm is an `Error::WeakKey`.
     pub fn sign(&self, key: &[u8]) -> Result<String> {
-        let header = self.header.to_base64()?;
-        let payload = self.payload.to_base64()?;
-        let data = format!("{}.{}", header, payload);
+        self.sign_with_options(key, &SignOptions::default())
+    }
+
+    /// Generate the signed token as `sign` does, adjusting what is signed with the options.
+    pub fn sign_with_options(&self, key: &[u8], options: &SignOptions) -> Result<String> {
+        let kid = if options.thumbprint_kid {
+            Some(keys::thumbprint(key, self.header.alg)?)
+        } else {
+            None
+        };
+        self.sign_stamped(key, kid.as_deref(), options)
+    }
+
+    /// Sign with a `kid` decided at signing time written into the header in place of any other,
+    /// along with the claims the options stamp into the payload.
+    fn sign_stamped(&self, key: &[u8], kid: Option<&str>, options: &SignOptions) -> Result<String> {
+        if !options.allow_weak_keys {
+            crypt::check_key_strength(key, &self.header.alg)?;
+        }
+        let data = self.encode_signing_input(kid, self.stamps(options)?)?;
 
-        let sig = crypt::sign(&*data, key, &self.header.alg)?;
+        let sig = crypt::sign(&data, key, &self.header.alg)?;
         Ok(format!("{}.{}", data, sig))
     }
+
+    /// The claims the options stamp into the signed payload.
+    fn stamps(&self, options: &SignOptions) -> Result<Map<String, Value>> {
+        let mut stamps = Map::new();
+        if options.issued_at {
+            stamps.insert("iat".into(), options.clock.now().timestamp().into());
+        }
+        if options.jti && self.payload.jti.is_none() {
+            stamps.insert("jti".into(), crypt::random_id()?.into());
+        }
+        Ok(stamps)
+    }
+
+    /// The signing input for a `Signer`, with the claims the options stamp. A thumbprint `kid`
+    /// needs the key itself, which a signer does not expose.
+    fn signer_input(&self, algorithm: Algorithm, options: &SignOptions) -> Result<String> {
+        self.check_algorithm(algorithm)?;
+        if options.thumbprint_kid {
+            return Err(format_err!(
+                "A thumbprint kid cannot be taken from a Signer's key."
+            ));
+        }
+        self.encode_signing_input(None, self.stamps(options)?)
+    }
+
+    /// Sign the token as `sign` does, keeping the signed form so that it can be verified and its
+    /// segments read without parsing it again.
+    pub fn sign_in_place(&mut self, key: &[u8]) -> Result<&str> {
+        self.sign_in_place_with_options(key, &SignOptions::default())
+    }
+
+    /// Sign in place as `sign_with_options` does. The `kid` and claims the options stamp are also
+    /// written into the token's own header and payload, so that they match what was signed.
+    pub fn sign_in_place_with_options(
+        &mut self,
+        key: &[u8],
+        options: &SignOptions,
+    ) -> Result<&str> {
+        if !options.allow_weak_keys {
+            crypt::check_key_strength(key, &self.header.alg)?;
+        }
+        if options.thumbprint_kid {
+            self.header.kid = Some(keys::thumbprint(key, self.header.alg)?);
+        }
+        if options.issued_at {
+            self.payload.iat = Some(NumericDate::from_secs(options.clock.now().timestamp())?);
+        }
+        if options.jti && self.payload.jti.is_none() {
+            self.payload.jti = Some(crypt::random_id()?);
+        }
+        let header = self.header.to_json_with_kid(None)?;
+        let payload = self.payload.to_json_stamped(Map::new())?;
+
+        let alg = self.header.alg;
+        let segments = Segments::sign(header, payload, |data| crypt::sign(data, key, &alg))?;
+        Ok(self.segments.insert(segments).raw())
+    }
+
+    /// Generate the signed token, getting the signature from a `Signer` rather than from a key
+    /// held in memory. The signer's algorithm must match the one in the header.
+    pub fn sign_with_signer<S: Signer + ?Sized>(&self, signer: &S) -> Result<String> {
+        self.sign_with_signer_and_options(signer, &SignOptions::default())
+    }
+
+    /// Sign with a `Signer` as `sign_with_signer` does, stamping the claims the options ask for.
+    /// Setting `thumbprint_kid` is an error, and `allow_weak_keys` is left to the signer.
+    pub fn sign_with_signer_and_options<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        options: &SignOptions,
+    ) -> Result<String> {
+        let data = self.signer_input(signer.algorithm(), options)?;
+
+        let sig = signer.sign(data.as_bytes())?;
+        Ok(format!("{}.{}", data, encode_config(&sig, URL_SAFE_NO_PAD)))
+    }
+
+    fn encode_signing_input(
+        &self,
+        kid: Option<&str>,
+        stamps: Map<String, Value>,
+    ) -> Result<String> {
+        let header = self.header.to_base64_with_kid(kid)?;
+        let payload = self.payload.to_base64_stamped(stamps)?;
+        Ok(format!("{}.{}", header, payload))
+    }
+
+    fn check_algorithm(&self, algorithm: Algorithm) -> Result<()> {
+        if algorithm == self.header.alg {
+            Ok(())
+        } else {
+            Err(format_err!(
+                "Signer uses {:?} but the header specifies {:?}.",
+                algorithm,
+                self.header.alg
+            ))
+        }
+    }
+}
+
+/// The exact form of a token that was parsed or signed in place, none of which a token built
+/// locally and not yet signed has.
+impl<H, C> Token<H, C> {
+    /// The compact serialization the token was parsed from or signed as.
+    pub fn raw(&self) -> Option<&str> {
+        self.segments.as_ref().map(Segments::raw)
+    }
+
+    /// The base64 encoded header as it was signed.
+    pub fn header_segment(&self) -> Option<&str> {
+        self.segments.as_ref().map(Segments::header_segment)
+    }
+
+    /// The base64 encoded payload as it was signed.
+    pub fn payload_segment(&self) -> Option<&str> {
+        self.segments.as_ref().map(Segments::payload_segment)
+    }
+
+    /// The base64 encoded signature.
+    pub fn signature_segment(&self) -> Option<&str> {
+        self.segments.as_ref().map(Segments::signature_segment)
+    }
+
+    /// The header and payload segments joined by a period, which is what the signature is over.
+    pub fn signing_input(&self) -> Option<&str> {
+        self.segments.as_ref().map(Segments::signing_input)
+    }
+
+    /// The JSON the header segment decodes to.
+    pub fn header_json(&self) -> Option<&[u8]> {
+        self.segments.as_ref().map(Segments::header)
+    }
+
+    /// The JSON the payload segment decodes to.
+    pub fn payload_json(&self) -> Option<&[u8]> {
+        self.segments.as_ref().map(Segments::payload)
+    }
+
+    /// The signature and the data it signs.
+    pub(crate) fn signed_parts(&self) -> Option<(&str, &str)> {
+        let segments = self.segments.as_ref()?;
+        Some((segments.signature_segment(), segments.signing_input()))
+    }
+}
+
+/// The checks shared by every way of verifying, which need nothing of the custom headers or claims.
+impl<H, C> Token<H, C> {
+    /// Verify the signature over the data it signs along with the claims, validating them once the
+    /// signature and times have passed.
+    pub(crate) fn verify_signed(
+        &self,
+        sig: &str,
+        data: &str,
+        key: &[u8],
+        options: &VerifyOptions,
+        validate: &dyn Fn(&Payload<C>) -> Result<()>,
+    ) -> Result<bool> {
+        if !options.allow_weak_keys {
+            crypt::check_key_strength(key, &self.header.alg)?;
+        }
+
+        let timely = self
+            .payload
+            .verify_with_leeway(&*options.clock, options.leeway);
+        if !(timely && crypt::verify(sig, data, key, &self.header.alg)?) {
+            return Ok(false);
+        }
+        self.accept_signed(options, validate)
+    }
+
+    /// The checks once the signature and times have passed: the validator, then the revocation
+    /// store and the replay cache.
+    pub(crate) fn accept_signed(
+        &self,
+        options: &VerifyOptions,
+        validate: &dyn Fn(&Payload<C>) -> Result<()>,
+    ) -> Result<bool> {
+        validate(&self.payload)?;
+        if let Some(ref store) = options.revocation_store {
+            let payload = &self.payload;
+            if store.is_revoked(payload.jti.as_deref(), payload.sub.as_deref(), payload.iat)? {
+                return Ok(false);
+            }
+        }
+        match options.replay_cache {
+            Some(ref cache) => self.check_replay(&**cache, options),
+            None => Ok(true),
+        }
+    }
+
+    // only a token that is otherwise valid is remembered, so a forgery cannot use up its jti
+    fn check_replay(&self, cache: &dyn ReplayCache, options: &VerifyOptions) -> Result<bool> {
+        let jti = match self.payload.jti {
+            Some(ref jti) => jti,
+            None => return Ok(false),
+        };
+        let until = match self.payload.exp {
+            Some(exp) => exp.to_datetime().checked_add_signed(options.leeway),
+            None => None,
+        };
+        cache.insert(jti, until, options.clock.now())
+    }
+}
+
+/// Asynchronous signing and verification, for keys and signatures that come from other services.
+/// These only build futures, leaving the choice of runtime to the caller.
+#[cfg(feature = "async")]
+impl<H, C> Token<H, C>
+where
+    H: Serialize + DeserializeOwned,
+    C: Serialize + DeserializeOwned,
+{
+    /// Generate the signed token, awaiting the signature from an `AsyncSigner`. The signer's
+    /// algorithm must match the one in the header.
+    pub async fn sign_with<S: AsyncSigner + ?Sized>(&self, signer: &S) -> Result<String> {
+        self.sign_with_and_options(signer, &SignOptions::default())
+            .await
+    }
+
+    /// Sign with an `AsyncSigner` as `sign_with` does, stamping the claims the options ask for as
+    /// `sign_with_signer_and_options` does.
+    pub async fn sign_with_and_options<S: AsyncSigner + ?Sized>(
+        &self,
+        signer: &S,
+        options: &SignOptions,
+    ) -> Result<String> {
+        let data = self.signer_input(signer.algorithm(), options)?;
+
+        let sig = signer.sign(data.as_bytes()).await?;
+        Ok(format!("{}.{}", data, encode_config(&sig, URL_SAFE_NO_PAD)))
+    }
+
+    /// Verify a parsed token with a key found by the resolver from the token's header. A key
+    /// resolved for another algorithm than the header's `alg` is an error. See
+    /// `UnverifiedToken::verify_with`.
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub async fn verify_with<R: KeyResolver<H> + ?Sized>(&self, resolver: &R) -> Result<bool> {
+        self.verify_with_and_validator(
+            resolver,
+            &VerifyOptions::default(),
+            &|_: &Payload<C>| Ok(()),
+        )
+        .await
+    }
+
+    /// Verify with a resolved key as `verify_with` does, adjusting the checks with the options and
+    /// then checking the claims with the validator as `verify_with_validator` does.
+    #[must_use = "the token is only verified when this returns `Ok(true)`"]
+    pub async fn verify_with_and_validator<R, V>(
+        &self,
+        resolver: &R,
+        options: &VerifyOptions,
+        validator: &V,
+    ) -> Result<bool>
+    where
+        R: KeyResolver<H> + ?Sized,
+        V: ClaimsValidator<C> + ?Sized,
+    {
+        if self.signed_parts().is_none() {
+            return Ok(false);
+        }
+        let resolved = resolver.resolve(&self.header).await?;
+        if resolved.algorithm != self.header.alg {
+            return Err(format_err!(
+                "Resolved a key for {:?} but the header specifies {:?}.",
+                resolved.algorithm,
+                self.header.alg
+            ));
+        }
+        self.verify_with_validator(&resolved.key, options, validator)
+    }
+}
+
+impl<H, C> fmt::Debug for Token<H, C>
+where
+    H: fmt::Debug,
+    C: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let redacted = self.raw().map(|_| "[REDACTED]");
+        let raw: &dyn fmt::Debug = if self.redact_raw {
+            &redacted
+        } else {
+            &self.raw()
+        };
+        f.debug_struct("Token")
+            .field("raw", raw)
+            .field("header", &self.header)
+            .field("payload", &self.payload)
+            .field("redact_raw", &self.redact_raw)
+            .finish()
+    }
 }
 
 impl<H, C> PartialEq for Token<H, C>
@@ -92,21 +529,58 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::Algorithm::{HS256, RS512};
-    use crate::{DefaultPayload, DefaultToken, Header, Payload, Token};
+    #[cfg(feature = "openssl")]
+    use super::Algorithm::RS512;
+    use super::Algorithm::{HS256, HS512};
+    use crate::{
+        keys, ClaimRule, DefaultPayload, DefaultToken, Error, FixedClock, Header, LocalSigner,
+        MemoryReplayCache, MemoryRevocationStore, NumericDate, Payload, SignOptions, Token,
+        VerifyOptions,
+    };
     use anyhow::Result;
     use chrono::{prelude::*, Duration};
-    use std::convert::TryInto;
+    use serde_json::Map;
+    use std::{convert::TryInto, sync::Arc};
+
+    const KEY: &[u8] = b"a 32 byte secret key for HS256!!";
 
     #[test]
     pub fn raw_data() {
         let raw = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
                    eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiYWRtaW4iOnRydWV9.\
                    TJVA95OrM7E2cBab30RMHrHDcEfxjoYZgeFONFh7HgQ";
-        let token = DefaultToken::<()>::parse(raw).unwrap();
+        let token = DefaultToken::<()>::from_raw(raw).unwrap();
 
         assert_eq!(token.header.alg, HS256);
-        assert!(token.verify(b"secret").unwrap());
+        let options = VerifyOptions {
+            allow_weak_keys: true,
+            ..VerifyOptions::default()
+        };
+        assert!(token.verify_with_options(b"secret", &options).unwrap());
+    }
+
+    #[test]
+    pub fn weak_hmac_key() {
+        let token: Token = Token::default();
+        let err = token.sign(b"secret").unwrap_err();
+        assert_eq!(
+            Some(&Error::WeakKey {
+                algorithm: HS256,
+                bits: 48,
+                minimum: 256,
+            }),
+            err.downcast_ref::<Error>()
+        );
+
+        let options = SignOptions {
+            allow_weak_keys: true,
+            ..SignOptions::default()
+        };
+        let raw = token.sign_with_options(b"secret", &options).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+        assert!(same.verify(b"secret").is_err());
+        assert!(token.sign(&KEY[..31]).is_err());
+        assert!(token.sign(KEY).is_ok());
     }
 
     #[test]
@@ -119,10 +593,11 @@ mod tests {
             ..DefaultPayload::default()
         };
         let token = Token::new(header, payload);
-        let key = b"secret";
+        let key = KEY;
         let raw = token.sign(key).unwrap();
-        let same = Token::parse(&*raw).unwrap();
+        let mut same = Token::from_raw(&raw).unwrap();
 
+        assert!(same.payload.jti.take().is_some());
         assert_eq!(token, same);
         assert!(same.verify(key).unwrap());
     }
@@ -131,12 +606,13 @@ mod tests {
     pub fn roundtrip_expired() -> Result<()> {
         let now = Utc::now();
         let token = create_for_range(now, now + Duration::minutes(-5))?;
-        let key = b"secret";
+        let key = KEY;
         let raw = token.sign(key)?;
-        let same = Token::parse(&*raw).unwrap();
+        let mut same = Token::from_raw(&raw).unwrap();
 
+        assert!(same.payload.jti.take().is_some());
         assert_eq!(token, same);
-        assert_eq!(false, same.verify(key).unwrap());
+        assert!(!same.verify(key).unwrap());
         Ok(())
     }
 
@@ -144,15 +620,159 @@ mod tests {
     pub fn roundtrip_not_yet_valid() -> Result<()> {
         let now = Utc::now();
         let token = create_for_range(now + Duration::minutes(5), now + Duration::minutes(10))?;
-        let key = b"secret";
+        let key = KEY;
         let raw = token.sign(key)?;
-        let same = Token::parse(&*raw).unwrap();
+        let mut same = Token::from_raw(&raw).unwrap();
 
+        assert!(same.payload.jti.take().is_some());
         assert_eq!(token, same);
-        assert_eq!(false, same.verify(key).unwrap());
+        assert!(!same.verify(key).unwrap());
         Ok(())
     }
 
+    #[test]
+    pub fn segments() {
+        let raw = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                   eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiYWRtaW4iOnRydWV9.\
+                   TJVA95OrM7E2cBab30RMHrHDcEfxjoYZgeFONFh7HgQ";
+        let token = DefaultToken::<()>::from_raw(raw).unwrap();
+
+        assert_eq!(Some(raw), token.raw());
+        assert_eq!(
+            Some("eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9"),
+            token.header_segment()
+        );
+        assert_eq!(
+            Some("eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiYWRtaW4iOnRydWV9"),
+            token.payload_segment()
+        );
+        assert_eq!(
+            Some("TJVA95OrM7E2cBab30RMHrHDcEfxjoYZgeFONFh7HgQ"),
+            token.signature_segment()
+        );
+        assert_eq!(Some(&raw[..raw.rfind('.').unwrap()]), token.signing_input());
+        assert_eq!(
+            Some(&br#"{"alg":"HS256","typ":"JWT"}"#[..]),
+            token.header_json()
+        );
+        assert_eq!(
+            Some(&br#"{"sub":"1234567890","name":"John Doe","admin":true}"#[..]),
+            token.payload_json()
+        );
+        assert!(Token::<()>::default().raw().is_none());
+        assert!(DefaultToken::<()>::from_raw("only.two").is_err());
+    }
+
+    #[test]
+    pub fn sign_in_place() {
+        let start = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+        let clock = Arc::new(FixedClock::new(start));
+        let mut token = create_for_range(start, start + Duration::minutes(5)).unwrap();
+        assert!(!token.verify(KEY).unwrap());
+
+        let options = SignOptions {
+            issued_at: true,
+            jti: true,
+            thumbprint_kid: true,
+            clock: clock.clone(),
+            ..SignOptions::default()
+        };
+        let raw = token
+            .sign_in_place_with_options(KEY, &options)
+            .unwrap()
+            .to_string();
+        let same: Token = Token::from_raw(&raw).unwrap();
+        let verify_options = VerifyOptions {
+            clock,
+            ..VerifyOptions::default()
+        };
+
+        assert_eq!(Some(raw.as_str()), token.raw());
+        assert_eq!(Some(NumericDate::from(start)), token.payload.iat);
+        assert!(token.payload.jti.is_some());
+        assert!(token.header.kid.is_some());
+        assert_eq!(same, token);
+        assert_eq!(same.payload_json(), token.payload_json());
+        assert!(token.verify_with_options(KEY, &verify_options).unwrap());
+    }
+
+    #[test]
+    pub fn resign_preserving() {
+        let raw = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                   eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiYWRtaW4iOnRydWV9.\
+                   TJVA95OrM7E2cBab30RMHrHDcEfxjoYZgeFONFh7HgQ";
+        let dropped: Token = Token::from_raw(raw).unwrap();
+        let kept: Token = Token::parse_preserving(raw).unwrap();
+
+        let same: Token = Token::parse_preserving(&dropped.sign(KEY).unwrap()).unwrap();
+        assert_eq!(Some(Map::new()), same.header.unknown);
+        assert_eq!(Some(Map::new()), same.payload.unknown);
+
+        let mut same: Token = Token::parse_preserving(&kept.sign(KEY).unwrap()).unwrap();
+        assert!(same.payload.jti.take().is_some());
+        assert_eq!(kept, same);
+        assert_eq!(Some(&"JWT".into()), same.header.unknown.unwrap().get("typ"));
+        let claims = same.payload.unknown.unwrap();
+        assert_eq!(Some(&"John Doe".into()), claims.get("name"));
+        assert_eq!(Some(&true.into()), claims.get("admin"));
+    }
+
+    #[test]
+    pub fn roundtrip_signer() {
+        let token: Token = Token::default();
+        let signer = LocalSigner::new(HS256, KEY);
+        let raw = token.sign_with_signer(&signer).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+
+        assert!(same.payload.jti.is_some());
+        assert!(same.verify(KEY).unwrap());
+
+        let unstamped = SignOptions {
+            jti: false,
+            ..SignOptions::default()
+        };
+        assert_eq!(
+            token.sign_with_options(KEY, &unstamped).unwrap(),
+            token
+                .sign_with_signer_and_options(&signer, &unstamped)
+                .unwrap()
+        );
+        let thumbprint = SignOptions {
+            thumbprint_kid: true,
+            ..SignOptions::default()
+        };
+        assert!(token
+            .sign_with_signer_and_options(&signer, &thumbprint)
+            .is_err());
+    }
+
+    #[test]
+    pub fn signer_algorithm_mismatch() {
+        let token: Token = Token::default();
+        let signer = LocalSigner::new(HS512, KEY);
+
+        assert!(token.sign_with_signer(&signer).is_err());
+    }
+
+    #[test]
+    pub fn thumbprint_kid() {
+        let key = keys::generate(HS256).unwrap();
+        let token: Token = Token::default();
+        let options = SignOptions {
+            thumbprint_kid: true,
+            ..SignOptions::default()
+        };
+        let raw = token
+            .sign_with_options(key.signing_key(), &options)
+            .unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+
+        assert_eq!(None, token.header.kid);
+        assert_eq!(Some(key.thumbprint().unwrap()), same.header.kid);
+        assert!(same.verify(key.verification_key()).unwrap());
+    }
+
+    #[cfg(feature = "openssl")]
     #[test]
     pub fn roundtrip_rsa() {
         let rsa_keypair = openssl::rsa::Rsa::generate(2048).unwrap();
@@ -167,14 +787,330 @@ mod tests {
         let raw = token
             .sign(&rsa_keypair.private_key_to_pem().unwrap())
             .unwrap();
-        let same = Token::parse(&*raw).unwrap();
+        let mut same = Token::from_raw(&raw).unwrap();
 
+        assert!(same.payload.jti.take().is_some());
         assert_eq!(token, same);
         assert!(same
             .verify(&rsa_keypair.public_key_to_pem().unwrap())
             .unwrap());
     }
 
+    #[test]
+    pub fn fixed_clock() {
+        let start = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+        let clock = Arc::new(FixedClock::new(start));
+        let token = create_for_range(start, start + Duration::minutes(5)).unwrap();
+        let sign_options = SignOptions {
+            issued_at: true,
+            clock: clock.clone(),
+            ..SignOptions::default()
+        };
+        let raw = token.sign_with_options(KEY, &sign_options).unwrap();
+        let same: Token = Token::from_raw(&raw).unwrap();
+        let verify_options = VerifyOptions {
+            clock: clock.clone(),
+            ..VerifyOptions::default()
+        };
+
+        assert_eq!(None, token.payload.iat);
+        assert_eq!(Some(NumericDate::from(start)), same.payload.iat);
+        assert!(!same.verify(KEY).unwrap());
+
+        clock.advance(Duration::minutes(1));
+        assert!(same.verify_with_options(KEY, &verify_options).unwrap());
+
+        clock.advance(Duration::minutes(5));
+        assert!(!same.verify_with_options(KEY, &verify_options).unwrap());
+    }
+
+    #[test]
+    pub fn replay() {
+        let start = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+        let clock = Arc::new(FixedClock::new(start));
+        let token = create_for_range(start, start + Duration::minutes(5)).unwrap();
+        let unstamped = SignOptions {
+            jti: false,
+            ..SignOptions::default()
+        };
+        let first: Token = Token::from_raw(&token.sign(KEY).unwrap()).unwrap();
+        let second: Token = Token::from_raw(&token.sign(KEY).unwrap()).unwrap();
+        let without: Token =
+            Token::from_raw(&token.sign_with_options(KEY, &unstamped).unwrap()).unwrap();
+        let options = VerifyOptions {
+            clock: clock.clone(),
+            replay_cache: Some(Arc::new(MemoryReplayCache::new(10))),
+            ..VerifyOptions::default()
+        };
+
+        assert_eq!(None, without.payload.jti);
+        assert_eq!(22, first.payload.jti.as_ref().unwrap().len());
+        assert_ne!(first.payload.jti, second.payload.jti);
+
+        assert!(first.verify_with_options(KEY, &options).unwrap());
+        assert!(!first.verify_with_options(KEY, &options).unwrap());
+        assert!(second.verify_with_options(KEY, &options).unwrap());
+        assert!(!without.verify_with_options(KEY, &options).unwrap());
+        assert!(without
+            .verify_with_options(
+                KEY,
+                &VerifyOptions {
+                    replay_cache: None,
+                    ..options.clone()
+                }
+            )
+            .unwrap());
+    }
+
+    #[test]
+    pub fn revoked() {
+        let store = Arc::new(MemoryRevocationStore::new());
+        let options = VerifyOptions {
+            revocation_store: Some(store.clone()),
+            ..VerifyOptions::default()
+        };
+        let token = Token {
+            payload: Payload {
+                sub: Some("alice".into()),
+                jti: Some("a".into()),
+                iat: Some(Utc::now().into()),
+                ..Payload::default()
+            },
+            ..DefaultToken::<()>::default()
+        };
+        let same: Token = Token::from_raw(&token.sign(KEY).unwrap()).unwrap();
+        assert!(same.verify_with_options(KEY, &options).unwrap());
+
+        store.revoke_subject("alice", (Utc::now() - Duration::hours(1)).into());
+        assert!(same.verify_with_options(KEY, &options).unwrap());
+
+        store.revoke_subject("alice", (Utc::now() + Duration::seconds(1)).into());
+        assert!(!same.verify_with_options(KEY, &options).unwrap());
+
+        let by_jti = Arc::new(MemoryRevocationStore::new());
+        let options = VerifyOptions {
+            revocation_store: Some(by_jti.clone()),
+            ..options
+        };
+        assert!(same.verify_with_options(KEY, &options).unwrap());
+        by_jti.revoke_jti("a");
+        assert!(!same.verify_with_options(KEY, &options).unwrap());
+    }
+
+    #[test]
+    pub fn validator() {
+        let token: Token = Token::new(
+            Header::default(),
+            Payload {
+                iss: Some("example.com".into()),
+                jti: Some("a".into()),
+                ..Payload::default()
+            },
+        );
+        let same: Token = Token::from_raw(&token.sign(KEY).unwrap()).unwrap();
+        let options = VerifyOptions {
+            replay_cache: Some(Arc::new(MemoryReplayCache::new(10))),
+            ..VerifyOptions::default()
+        };
+        let wrong = ClaimRule::one_of("iss", vec!["a.example.com", "b.example.com"]);
+
+        assert!(same
+            .verify_with_validator(KEY, &options, &wrong)
+            .unwrap_err()
+            .downcast_ref::<Error>()
+            .is_some());
+        assert!(!same
+            .verify_with_validator(b"a different 32 byte secret key!!", &options, &wrong)
+            .unwrap());
+        // the rejected token was not remembered as seen
+        let right = ClaimRule::equals("iss", "example.com");
+        assert!(same.verify_with_validator(KEY, &options, &right).unwrap());
+        assert!(!same.verify_with_validator(KEY, &options, &right).unwrap());
+    }
+
+    #[test]
+    pub fn keeps_own_jti() {
+        let token = Token {
+            payload: Payload {
+                jti: Some("mine".into()),
+                ..Payload::default()
+            },
+            ..DefaultToken::<()>::default()
+        };
+        let options = SignOptions {
+            jti: true,
+            ..SignOptions::default()
+        };
+        let same: Token =
+            Token::from_raw(&token.sign_with_options(KEY, &options).unwrap()).unwrap();
+
+        assert_eq!(Some("mine".to_string()), same.payload.jti);
+    }
+
+    #[test]
+    pub fn leeway() {
+        let start = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+        let clock = Arc::new(FixedClock::new(start - Duration::seconds(30)));
+        let token = create_for_range(start, start + Duration::minutes(5)).unwrap();
+        let same: Token = Token::from_raw(&token.sign(KEY).unwrap()).unwrap();
+        let strict = VerifyOptions {
+            clock: clock.clone(),
+            ..VerifyOptions::default()
+        };
+        let lenient = VerifyOptions {
+            leeway: Duration::seconds(30),
+            ..strict.clone()
+        };
+
+        assert!(!same.verify_with_options(KEY, &strict).unwrap());
+        assert!(same.verify_with_options(KEY, &lenient).unwrap());
+
+        clock.set(start + Duration::minutes(5) + Duration::seconds(29));
+        assert!(!same.verify_with_options(KEY, &strict).unwrap());
+        assert!(same.verify_with_options(KEY, &lenient).unwrap());
+
+        clock.advance(Duration::seconds(1));
+        assert!(!same.verify_with_options(KEY, &lenient).unwrap());
+    }
+
+    #[test]
+    pub fn redacted_debug() {
+        let raw = Token::<()>::default().sign(KEY).unwrap();
+        let mut token: Token = Token::from_raw(&raw).unwrap();
+        assert!(format!("{:?}", token).contains(&raw));
+
+        token.redact_raw = true;
+        let debug = format!("{:?}", token);
+        assert!(!debug.contains(&raw));
+        assert!(debug.contains("raw: Some(\"[REDACTED]\")"));
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    pub fn weak_rsa_key() {
+        let rsa_keypair = openssl::rsa::Rsa::generate(1024).unwrap();
+        let token: Token = Token {
+            header: Header {
+                alg: RS512,
+                ..Header::default()
+            },
+            ..Token::default()
+        };
+        let err = token
+            .sign(&rsa_keypair.private_key_to_pem().unwrap())
+            .unwrap_err();
+
+        assert_eq!(
+            Some(&Error::WeakKey {
+                algorithm: RS512,
+                bits: 1024,
+                minimum: 2048,
+            }),
+            err.downcast_ref::<Error>()
+        );
+    }
+
+    #[cfg(feature = "async")]
+    mod asynchronous {
+        use super::KEY;
+        use crate::{
+            Algorithm::{HS256, RS256},
+            ClaimRule, Error, Header, KeyResolver, LocalSigner, Payload, ResolvedKey, Result,
+            Token, VerifyOptions,
+        };
+        use anyhow::format_err;
+        use std::{future::Future, pin::Pin};
+
+        /// Stands in for a remote key set, knowing the key for one `kid` and claiming another is
+        /// an RSA public key.
+        struct Keys;
+
+        impl KeyResolver<()> for Keys {
+            fn resolve<'a>(
+                &'a self,
+                header: &'a Header,
+            ) -> Pin<Box<dyn Future<Output = Result<ResolvedKey>> + Send + 'a>> {
+                let found = match header.kid.as_deref() {
+                    Some("current") => Ok(ResolvedKey::new(HS256, KEY)),
+                    Some("public") => Ok(ResolvedKey::new(RS256, KEY)),
+                    _ => Err(format_err!("Unknown key.")),
+                };
+                Box::pin(async move { found })
+            }
+        }
+
+        fn token(kid: &str) -> Token {
+            Token::new(
+                Header {
+                    kid: Some(kid.into()),
+                    ..Header::default()
+                },
+                Default::default(),
+            )
+        }
+
+        #[tokio::test]
+        async fn roundtrip_async() {
+            let token = token("current");
+            let raw = token
+                .sign_with(&LocalSigner::new(HS256, KEY))
+                .await
+                .unwrap();
+            let mut same = Token::<()>::from_raw(&raw).unwrap();
+
+            assert!(same.payload.jti.take().is_some());
+            assert_eq!(token, same);
+            assert!(same.verify_with(&Keys).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn unresolved_key() {
+            let raw = token("retired").sign(KEY).unwrap();
+            let same = Token::<()>::from_raw(&raw).unwrap();
+
+            assert!(same.verify_with(&Keys).await.is_err());
+        }
+
+        #[tokio::test]
+        async fn resolved_for_another_algorithm() {
+            let raw = token("public").sign(KEY).unwrap();
+            let same = Token::<()>::from_raw(&raw).unwrap();
+
+            assert!(same.verify_with(&Keys).await.is_err());
+        }
+
+        #[tokio::test]
+        async fn validator_async() {
+            let token = Token {
+                payload: Payload {
+                    sub: Some("alice".into()),
+                    ..Payload::default()
+                },
+                ..token("current")
+            };
+            let same = Token::<()>::from_raw(&token.sign(KEY).unwrap()).unwrap();
+            let options = VerifyOptions::default();
+
+            assert!(same
+                .verify_with_and_validator(&Keys, &options, &ClaimRule::equals("sub", "alice"))
+                .await
+                .unwrap());
+            let err = same
+                .verify_with_and_validator(&Keys, &options, &ClaimRule::equals("sub", "bob"))
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::InvalidClaim { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn unsigned_async() {
+            assert!(!token("current").verify_with(&Keys).await.unwrap());
+        }
+    }
+
     fn create_for_range(nbf: DateTime<Utc>, exp: DateTime<Utc>) -> Result<Token> {
         let header: Header = Header::default();
         let payload = Payload {
@@ -0,0 +1,65 @@
+use crate::{
+    clock::{Clock, SystemClock},
+    replay::ReplayCache,
+    revocation::RevocationStore,
+};
+use chrono::Duration;
+use std::sync::Arc;
+
+/// Options for signing a token beyond the key itself. Start from the default and set what is
+/// needed with struct update syntax.
+#[derive(Debug, Clone)]
+pub struct SignOptions {
+    /// Write the RFC 7638 thumbprint of the signing key into the signed header as its `kid`,
+    /// replacing any other. The token's own header is left as it is.
+    pub thumbprint_kid: bool,
+    /// Sign with keys below the RFC 7518 minimums, only for interoperating with legacy systems.
+    pub allow_weak_keys: bool,
+    /// Stamp the signed payload's `iat` with the clock's current time, replacing any other.
+    pub issued_at: bool,
+    /// Stamp the signed payload with a random 128 bit `jti` when it has none of its own. On by
+    /// default, so that every token can be told apart by a `ReplayCache`.
+    pub jti: bool,
+    pub clock: Arc<dyn Clock>,
+}
+
+impl Default for SignOptions {
+    fn default() -> SignOptions {
+        SignOptions {
+            thumbprint_kid: false,
+            allow_weak_keys: false,
+            issued_at: false,
+            jti: true,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+/// Options for verifying a token beyond the key itself, built the same way as `SignOptions`.
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// Verify with keys below the RFC 7518 minimums, only for interoperating with legacy systems.
+    pub allow_weak_keys: bool,
+    /// The clock `nbf` and `exp` are checked against.
+    pub clock: Arc<dyn Clock>,
+    /// How far the clock may be from the issuer's, accepting a token that early before its `nbf`
+    /// and that late after its `exp`.
+    pub leeway: Duration,
+    /// Reject a token whose `jti` the cache has already seen, or that has no `jti`, remembering it
+    /// until its `exp` and the leeway have passed.
+    pub replay_cache: Option<Arc<dyn ReplayCache>>,
+    /// Reject a token the store has revoked by its `jti` or its `sub` and `iat`.
+    pub revocation_store: Option<Arc<dyn RevocationStore>>,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> VerifyOptions {
+        VerifyOptions {
+            allow_weak_keys: false,
+            clock: Arc::new(SystemClock),
+            leeway: Duration::zero(),
+            replay_cache: None,
+            revocation_store: None,
+        }
+    }
+}
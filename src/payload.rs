@@ -1,9 +1,20 @@
 use super::Result;
+use crate::{
+    clock::{Clock, SystemClock},
+    crypt,
+    date::NumericDate,
+    error::{json_type, Error},
+    header::{merge_custom, unknown_members},
+};
 use anyhow::format_err;
 use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::Duration;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use serde_json::value::Value;
+use serde_json::{Map, Value};
+use std::{iter::FromIterator, sync::Arc};
+
+/// The names of the registered claims `Payload` has fields for.
+pub(crate) const REGISTERED_CLAIMS: [&str; 7] = ["iss", "sub", "aud", "exp", "nbf", "iat", "jti"];
 
 /// A default claim set, including the standard, or registered, claims and the ability to specify
 /// your own as custom claims.
@@ -16,15 +27,19 @@ pub struct Payload<T = ()> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub aud: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub exp: Option<u64>,
+    pub exp: Option<NumericDate>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub nbf: Option<u64>,
+    pub nbf: Option<NumericDate>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub iat: Option<u64>,
+    pub iat: Option<NumericDate>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub jti: Option<String>,
     #[serde(skip_serializing)]
     pub claims: Option<T>,
+    /// Claims that are neither registered nor custom, kept by `from_base64_preserving` and written
+    /// out again when encoding, unless already set by another field.
+    #[serde(skip)]
+    pub unknown: Option<Map<String, Value>>,
 }
 
 /// A convenient type alias that assumes the standard claims are sufficient, the empty tuple type
@@ -32,86 +47,361 @@ pub struct Payload<T = ()> {
 pub type DefaultPayload = Payload<()>;
 
 impl<T: Serialize + DeserializeOwned> Payload<T> {
+    /// Start building a payload whose times are relative to the system clock, or to another given
+    /// to the builder.
+    pub fn builder() -> PayloadBuilder<T> {
+        PayloadBuilder {
+            payload: Payload {
+                iss: None,
+                sub: None,
+                aud: None,
+                exp: None,
+                nbf: None,
+                iat: None,
+                jti: None,
+                claims: None,
+                unknown: None,
+            },
+            clock: Arc::new(SystemClock),
+            expires_in: None,
+            not_before_now: false,
+            issued_now: false,
+            random_jti: false,
+        }
+    }
+
     /// This implementation simply parses the base64 data twice, first parsing out the standard
     /// claims then any custom claims, assigning the latter into a copy of the former before
     /// returning registered and custom claims.
     pub fn from_base64(raw: &str) -> Result<Payload<T>> {
         let data = decode_config(raw, URL_SAFE_NO_PAD)?;
+        Payload::from_slice(&data)
+    }
 
-        let claims: Payload<T> = serde_json::from_slice(&data)?;
-
-        let custom: Option<T> = serde_json::from_slice(&data).ok();
+    /// Decode from base64 as `from_base64` does, also keeping any unknown claims so that
+    /// re-encoding the payload does not drop them.
+    pub fn from_base64_preserving(raw: &str) -> Result<Payload<T>> {
+        let data = decode_config(raw, URL_SAFE_NO_PAD)?;
+        Payload::from_slice_preserving(&data)
+    }
 
+    pub(crate) fn from_slice_preserving(data: &[u8]) -> Result<Payload<T>> {
+        let payload: Payload<T> = Payload::from_slice(data)?;
+        let unknown = unknown_members(data, &payload, payload.claims.as_ref())?;
         Ok(Payload {
-            iss: claims.iss,
-            sub: claims.sub,
-            aud: claims.aud,
-            exp: claims.exp,
-            nbf: claims.nbf,
-            iat: claims.iat,
-            jti: claims.jti,
-            claims: custom,
+            unknown: Some(unknown),
+            ..payload
         })
     }
 
     /// Renders both the standard and custom claims into a single consolidated JSON representation
     /// before encoding.
     pub fn to_base64(&self) -> Result<String> {
-        if let Value::Object(mut claims_map) = serde_json::to_value(&self)? {
-            match self.claims {
-                Some(ref custom) => {
-                    if let Value::Object(custom_map) = serde_json::to_value(&custom)? {
-                        claims_map.extend(custom_map);
-                        let s = serde_json::to_string(&claims_map)?;
-                        let enc = encode_config((&*s).as_bytes(), URL_SAFE_NO_PAD);
-                        Ok(enc)
-                    } else {
-                        Err(format_err!("Could not access custom claims."))
+        self.to_base64_stamped(Map::new())
+    }
+
+    /// Encode to a string, with claims decided at signing time in place of any others.
+    pub(crate) fn to_base64_stamped(&self, stamps: Map<String, Value>) -> Result<String> {
+        let s = self.to_json_stamped(stamps)?;
+        Ok(encode_config(s.as_bytes(), URL_SAFE_NO_PAD))
+    }
+
+    /// The JSON that `to_base64_stamped` encodes.
+    pub(crate) fn to_json_stamped(&self, stamps: Map<String, Value>) -> Result<String> {
+        let mut claims_map = self.to_map()?;
+        claims_map.extend(stamps);
+        Ok(serde_json::to_string(&claims_map)?)
+    }
+}
+
+impl<T: Serialize> Payload<T> {
+    /// The scopes the token grants, from its `scope` claim, its `scp` claim or both together, and
+    /// none when it has neither. Either may be a space-delimited string or an array of strings.
+    pub fn scopes(&self) -> Result<Scopes> {
+        Scopes::from_claims(&self.to_map()?)
+    }
+
+    /// Every claim as it would be encoded, registered, custom and unknown, without any stamps.
+    pub(crate) fn to_map(&self) -> Result<Map<String, Value>> {
+        if let Value::Object(mut claims_map) = serde_json::to_value(self)? {
+            if let Some(ref custom) = self.claims {
+                match serde_json::to_value(custom)? {
+                    Value::Object(custom_map) => {
+                        merge_custom(&mut claims_map, custom_map, "claim")?
+                    }
+                    other => {
+                        return Err(Error::NotAnObject {
+                            what: "custom claims",
+                            found: json_type(&other),
+                        }
+                        .into())
                     }
                 }
-                None => {
-                    let s = serde_json::to_string(&claims_map)?;
-                    let enc = encode_config((&*s).as_bytes(), URL_SAFE_NO_PAD);
-                    Ok(enc)
+            }
+            if let Some(ref unknown) = self.unknown {
+                for (name, value) in unknown {
+                    claims_map.entry(name).or_insert_with(|| value.clone());
                 }
             }
+            Ok(claims_map)
         } else {
             Err(format_err!("Could not access standard claims.",))
         }
     }
+}
 
+/// Time checks, which need nothing of the custom claims.
+impl<T> Payload<T> {
+    /// Check `nbf` and `exp` against the system clock.
     pub fn verify(&self) -> bool {
-        let now = Utc::now();
-        let nbf_verified = match self.nbf {
-            Some(nbf_sec) => {
-                let nbf = DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp(nbf_sec as i64, 0),
-                    Utc,
-                );
-                nbf < now
-            }
-            None => true,
+        self.verify_with_clock(&SystemClock)
+    }
+
+    /// Check `nbf` and `exp` against the given clock.
+    pub fn verify_with_clock(&self, clock: &dyn Clock) -> bool {
+        self.verify_with_leeway(clock, Duration::zero())
+    }
+
+    /// Check `nbf` and `exp` against the given clock, allowing for it to differ from the issuer's
+    /// by up to the leeway. Following RFC 7519, the token is valid from its `nbf` onward and up to
+    /// but not including its `exp`, so a token whose `nbf` is the current second is accepted and
+    /// one whose `exp` is rejected.
+    pub fn verify_with_leeway(&self, clock: &dyn Clock, leeway: Duration) -> bool {
+        let now = clock.now();
+        // a leeway so large the time overflows cannot rule anything out
+        let nbf_verified = match (self.nbf, now.checked_add_signed(leeway)) {
+            (Some(nbf), Some(late)) => nbf <= NumericDate::from(late),
+            _ => true,
         };
-        let exp_verified = match self.exp {
-            Some(exp_sec) => {
-                let exp = DateTime::<Utc>::from_utc(
-                    NaiveDateTime::from_timestamp(exp_sec as i64, 0),
-                    Utc,
-                );
-                now < exp
-            }
-            None => true,
+        let exp_verified = match (self.exp, now.checked_sub_signed(leeway)) {
+            (Some(exp), Some(early)) => NumericDate::from(early) < exp,
+            _ => true,
         };
         nbf_verified && exp_verified
     }
 }
 
+impl<'de, T: Deserialize<'de>> Payload<T> {
+    /// Decode from JSON already decoded from base64, letting the custom claims borrow from it.
+    pub(crate) fn from_slice(data: &'de [u8]) -> Result<Payload<T>> {
+        let claims: Payload<T> = serde_json::from_slice(data)?;
+
+        let custom: Option<T> = serde_json::from_slice(data).ok();
+
+        Ok(Payload {
+            iss: claims.iss,
+            sub: claims.sub,
+            aud: claims.aud,
+            exp: claims.exp,
+            nbf: claims.nbf,
+            iat: claims.iat,
+            jti: claims.jti,
+            claims: custom,
+            unknown: None,
+        })
+    }
+}
+
+/// Builds a payload with times relative to a clock, all of them taken from a single reading of the
+/// clock once the payload is built.
+#[derive(Debug)]
+pub struct PayloadBuilder<T = ()> {
+    payload: Payload<T>,
+    clock: Arc<dyn Clock>,
+    expires_in: Option<Duration>,
+    not_before_now: bool,
+    issued_now: bool,
+    random_jti: bool,
+}
+
+impl<T: Serialize + DeserializeOwned> PayloadBuilder<T> {
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> PayloadBuilder<T> {
+        self.clock = clock;
+        self
+    }
+
+    pub fn issuer(mut self, iss: &str) -> PayloadBuilder<T> {
+        self.payload.iss = Some(iss.into());
+        self
+    }
+
+    pub fn subject(mut self, sub: &str) -> PayloadBuilder<T> {
+        self.payload.sub = Some(sub.into());
+        self
+    }
+
+    pub fn audience(mut self, aud: &str) -> PayloadBuilder<T> {
+        self.payload.aud = Some(aud.into());
+        self
+    }
+
+    pub fn jti(mut self, jti: &str) -> PayloadBuilder<T> {
+        self.payload.jti = Some(jti.into());
+        self.random_jti = false;
+        self
+    }
+
+    /// Set `jti` to a random 128 bit identifier when the payload is built.
+    pub fn random_jti(mut self) -> PayloadBuilder<T> {
+        self.random_jti = true;
+        self
+    }
+
+    pub fn claims(mut self, claims: T) -> PayloadBuilder<T> {
+        self.payload.claims = Some(claims);
+        self
+    }
+
+    /// Set `exp` to this long after the time the payload is built.
+    pub fn expires_in(mut self, lifetime: Duration) -> PayloadBuilder<T> {
+        self.expires_in = Some(lifetime);
+        self
+    }
+
+    /// Set `nbf` to the time the payload is built.
+    pub fn not_before_now(mut self) -> PayloadBuilder<T> {
+        self.not_before_now = true;
+        self
+    }
+
+    /// Set `iat` to the time the payload is built.
+    pub fn issued_now(mut self) -> PayloadBuilder<T> {
+        self.issued_now = true;
+        self
+    }
+
+    /// Read the clock and fill in the times, failing only when a random `jti` cannot be generated
+    /// or the expiry is beyond the supported range of dates.
+    pub fn build(self) -> Result<Payload<T>> {
+        let mut payload = self.payload;
+        let now = self.clock.now();
+        if let Some(lifetime) = self.expires_in {
+            let exp = now
+                .checked_add_signed(lifetime)
+                .ok_or_else(|| format_err!("The expiry is beyond the supported range of dates."))?;
+            payload.exp = Some(exp.into());
+        }
+        if self.not_before_now {
+            payload.nbf = Some(now.into());
+        }
+        if self.issued_now {
+            payload.iat = Some(now.into());
+        }
+        if self.random_jti {
+            payload.jti = Some(crypt::random_id()?);
+        }
+        Ok(payload)
+    }
+}
+
+/// The OAuth 2.0 scopes a token grants, read from either a space-delimited `scope` claim, as RFC
+/// 8693 has it, or a `scp` array. It serializes as a space-delimited string and deserializes from
+/// either form, so it can also be a field of custom claims.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scopes(Vec<String>);
+
+impl Scopes {
+    /// Split a space-delimited list of scopes, ignoring repeated spaces.
+    pub fn parse(scope: &str) -> Scopes {
+        scope.split(' ').filter(|s| !s.is_empty()).collect()
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.0.iter().any(|held| held == scope)
+    }
+
+    pub fn has_all<'a, I: IntoIterator<Item = &'a str>>(&self, scopes: I) -> bool {
+        scopes.into_iter().all(|scope| self.has_scope(scope))
+    }
+
+    pub fn has_any<'a, I: IntoIterator<Item = &'a str>>(&self, scopes: I) -> bool {
+        scopes.into_iter().any(|scope| self.has_scope(scope))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Read the scopes from the `scope` and `scp` claims of a payload already turned into a map.
+    pub(crate) fn from_claims(claims: &Map<String, Value>) -> Result<Scopes> {
+        let mut scopes = Vec::new();
+        for &name in &["scope", "scp"] {
+            if let Some(value) = claims.get(name) {
+                scopes.extend(Scopes::from_claim(name, value)?.0);
+            }
+        }
+        Ok(scopes.into_iter().collect())
+    }
+
+    /// Read the scopes from a claim holding either form, naming the claim in the error otherwise.
+    fn from_claim(name: &str, value: &Value) -> Result<Scopes> {
+        Scopes::deserialize(value).map_err(|_| {
+            Error::ClaimType {
+                name: name.into(),
+                expected: "a string or an array of strings",
+                found: json_type(value),
+            }
+            .into()
+        })
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for Scopes {
+    fn from_iter<I: IntoIterator<Item = S>>(scopes: I) -> Scopes {
+        let mut collected = Scopes::default();
+        for scope in scopes {
+            let scope = scope.into();
+            if !collected.has_scope(&scope) {
+                collected.0.push(scope);
+            }
+        }
+        collected
+    }
+}
+
+impl Serialize for Scopes {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.join(" "))
+    }
+}
+
+impl<'de> Deserialize<'de> for Scopes {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Form {
+            Delimited(String),
+            List(Vec<String>),
+        }
+        Ok(match Form::deserialize(deserializer)? {
+            Form::Delimited(scope) => Scopes::parse(&scope),
+            Form::List(scopes) => scopes.into_iter().collect(),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{DefaultPayload, Payload};
+    use super::{DefaultPayload, Payload, Scopes, REGISTERED_CLAIMS};
+    use crate::{clock::FixedClock, date::NumericDate, Error};
+    use base64::{encode_config, URL_SAFE_NO_PAD};
     use chrono::{prelude::*, Duration};
     use serde::{Deserialize, Serialize};
-    use std::default::Default;
+    use serde_json::{json, Map, Value};
+    use std::{convert::TryFrom, default::Default, sync::Arc};
 
     #[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
     struct CustomClaims {
@@ -157,17 +447,14 @@ mod tests {
     fn roundtrip() {
         let payload = create_default();
         let enc = payload.to_base64().unwrap();
-        assert_eq!(payload, Payload::from_base64(&*enc).unwrap());
+        assert_eq!(payload, Payload::from_base64(&enc).unwrap());
     }
 
     #[test]
     fn roundtrip_custom() {
         let payload = create_custom();
         let enc = payload.to_base64().unwrap();
-        assert_eq!(
-            payload,
-            Payload::<CustomClaims>::from_base64(&*enc).unwrap()
-        );
+        assert_eq!(payload, Payload::<CustomClaims>::from_base64(&enc).unwrap());
     }
 
     #[test]
@@ -179,7 +466,7 @@ mod tests {
     #[test]
     fn fail_nbf() {
         let payload = create_with_nbf(-5);
-        assert_eq!(false, payload.verify());
+        assert!(!payload.verify());
     }
 
     #[test]
@@ -191,7 +478,7 @@ mod tests {
     #[test]
     fn fail_exp() {
         let payload = create_with_exp(-5);
-        assert_eq!(false, payload.verify());
+        assert!(!payload.verify());
     }
 
     #[test]
@@ -203,17 +490,369 @@ mod tests {
     #[test]
     fn fail_nbf_exp() {
         let payload = create_with_nbf_exp(-5, -5);
-        assert_eq!(false, payload.verify());
+        assert!(!payload.verify());
         let payload = create_with_nbf_exp(5, -5);
-        assert_eq!(false, payload.verify());
+        assert!(!payload.verify());
         let payload = create_with_nbf_exp(-5, 5);
-        assert_eq!(false, payload.verify());
+        assert!(!payload.verify());
+    }
+
+    #[test]
+    fn verify_with_fixed_clock() {
+        let payload = create_default();
+        let clock = FixedClock::new(Utc.timestamp_opt(1_302_318_000, 0).unwrap());
+        assert!(payload.verify_with_clock(&clock));
+
+        clock.set(Utc.timestamp_opt(1_302_317_000, 0).unwrap());
+        assert!(!payload.verify_with_clock(&clock));
+
+        clock.set(Utc.timestamp_opt(1_302_320_000, 0).unwrap());
+        assert!(!payload.verify_with_clock(&clock));
+        assert!(!payload.verify());
+    }
+
+    #[test]
+    fn boundaries() {
+        let nbf = 1_302_317_100;
+        let exp = 1_302_318_100;
+        let payload = DefaultPayload {
+            nbf: Some(date(nbf)),
+            exp: Some(date(exp)),
+            ..Default::default()
+        };
+        let at = |secs: i64, nanos: u32| FixedClock::new(Utc.timestamp_opt(secs, nanos).unwrap());
+
+        // (seconds, nanoseconds, leeway in seconds, valid)
+        let cases = [
+            (nbf - 1, 999_999_999, 0, false),
+            (nbf, 0, 0, true),
+            (nbf, 1, 0, true),
+            (exp - 1, 999_999_999, 0, true),
+            (exp, 0, 0, false),
+            (exp, 1, 0, false),
+            (nbf - 30, 0, 30, true),
+            (nbf - 31, 0, 30, false),
+            (exp + 29, 999_999_999, 30, true),
+            (exp + 30, 0, 30, false),
+        ];
+        for &(secs, nanos, leeway, valid) in &cases {
+            let clock = at(secs, nanos);
+            assert_eq!(
+                valid,
+                payload.verify_with_leeway(&clock, Duration::seconds(leeway)),
+                "at {}.{:09} with {}s leeway",
+                secs,
+                nanos,
+                leeway
+            );
+        }
+        assert!(payload.verify_with_clock(&at(nbf, 0)));
+        assert!(!payload.verify_with_clock(&at(exp, 0)));
+    }
+
+    #[test]
+    fn fractional_boundaries() {
+        let payload = DefaultPayload {
+            nbf: Some(NumericDate::try_from(1_302_317_100.5).unwrap()),
+            exp: Some(NumericDate::try_from(1_302_318_100.5).unwrap()),
+            ..Default::default()
+        };
+        let at = |nanos: i64| {
+            let start = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+            FixedClock::new(start + Duration::nanoseconds(nanos))
+        };
+
+        assert!(!payload.verify_with_clock(&at(499_999_999)));
+        assert!(payload.verify_with_clock(&at(500_000_000)));
+        assert!(payload.verify_with_clock(&at(1_000_499_999_999)));
+        assert!(!payload.verify_with_clock(&at(1_000_500_000_000)));
+    }
+
+    #[test]
+    fn builder() {
+        let start = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+        let clock = Arc::new(FixedClock::new(start));
+        let payload = DefaultPayload::builder()
+            .issuer("example.com")
+            .subject("Random User")
+            .audience("api")
+            .expires_in(Duration::minutes(5))
+            .not_before_now()
+            .issued_now()
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            DefaultPayload {
+                iss: Some("example.com".into()),
+                sub: Some("Random User".into()),
+                aud: Some("api".into()),
+                exp: Some(date(1_302_317_400)),
+                nbf: Some(date(1_302_317_100)),
+                iat: Some(date(1_302_317_100)),
+                ..Default::default()
+            },
+            payload
+        );
+        assert!(payload.verify_with_clock(&*clock));
+        clock.advance(Duration::minutes(5));
+        assert!(!payload.verify_with_clock(&*clock));
+    }
+
+    #[test]
+    fn builder_claims_and_jti() {
+        let payload = Payload::builder()
+            .claims(create_custom().claims.unwrap())
+            .random_jti()
+            .build()
+            .unwrap();
+        let other = DefaultPayload::builder().random_jti().build().unwrap();
+        let fixed = DefaultPayload::builder()
+            .random_jti()
+            .jti("one")
+            .build()
+            .unwrap();
+
+        assert_eq!(create_custom().claims, payload.claims);
+        assert_eq!(None, payload.exp);
+        assert_eq!(22, payload.jti.as_ref().unwrap().len());
+        assert_ne!(payload.jti, other.jti);
+        assert_eq!(Some("one".to_string()), fixed.jti);
+    }
+
+    #[test]
+    fn fractional_dates() {
+        // {"exp":1700000000.5,"nbf":-0.25}
+        let enc = "eyJleHAiOjE3MDAwMDAwMDAuNSwibmJmIjotMC4yNX0";
+        let payload: DefaultPayload = Payload::from_base64(enc).unwrap();
+        let exp = payload.exp.unwrap();
+
+        assert_eq!(
+            (1_700_000_000, 500_000_000),
+            (exp.secs(), exp.subsec_nanos())
+        );
+        assert_eq!(-1, payload.nbf.unwrap().secs());
+        assert_eq!(enc, payload.to_base64().unwrap());
+    }
+
+    #[test]
+    fn out_of_range_date() {
+        // {"exp":1e300}
+        assert!(DefaultPayload::from_base64("eyJleHAiOjFlMzAwfQ").is_err());
+    }
+
+    #[test]
+    fn stamped_claims() {
+        let payload = create_default();
+        let mut stamps = Map::new();
+        stamps.insert("iat".into(), 1_302_318_000.into());
+        let enc = payload.to_base64_stamped(stamps).unwrap();
+
+        let stamped: DefaultPayload = Payload::from_base64(&enc).unwrap();
+        assert_eq!(Some(date(1_302_318_000)), stamped.iat);
+        assert_eq!(payload.exp, stamped.exp);
+    }
+
+    #[test]
+    fn preserve_unknown() {
+        // {"iss":"example.com","user_id":"123456","is_admin":false,"role":"auditor","tier":2}
+        let enc = "eyJpc3MiOiJleGFtcGxlLmNvbSIsInVzZXJfaWQiOiIxMjM0NTYiLCJpc19hZG1pbiI6ZmFsc2UsInJvbGUiOiJhdWRpdG9yIiwidGllciI6Mn0";
+        let dropped: Payload<CustomClaims> = Payload::from_base64(enc).unwrap();
+        let mut kept: Payload<CustomClaims> = Payload::from_base64_preserving(enc).unwrap();
+
+        let mut unknown = Map::new();
+        unknown.insert("role".into(), "auditor".into());
+        unknown.insert("tier".into(), 2.into());
+        assert_eq!(None, dropped.unknown);
+        assert_eq!(Some(unknown), kept.unknown);
+
+        kept.claims.as_mut().unwrap().is_admin = true;
+        let again: Payload<CustomClaims> =
+            Payload::from_base64_preserving(&kept.to_base64().unwrap()).unwrap();
+        assert_eq!(kept, again);
+        assert!(again.claims.unwrap().is_admin);
+
+        let again: Payload<CustomClaims> =
+            Payload::from_base64_preserving(&dropped.to_base64().unwrap()).unwrap();
+        assert_eq!(Some(Map::new()), again.unknown);
+    }
+
+    #[test]
+    fn custom_collisions() {
+        let registered = || {
+            let base = create_default();
+            Payload {
+                iss: base.iss,
+                sub: base.sub,
+                aud: base.aud,
+                exp: base.exp,
+                nbf: base.nbf,
+                iat: base.iat,
+                jti: Some("a".into()),
+                claims: None,
+                unknown: None,
+            }
+        };
+        let encoded = serde_json::to_value(registered()).unwrap();
+        for &name in &REGISTERED_CLAIMS {
+            let claim = |value: Value| {
+                let mut custom = Map::new();
+                custom.insert(name.into(), value);
+                Some(custom)
+            };
+            let other = match name {
+                "exp" | "nbf" | "iat" => Value::from(1),
+                _ => Value::from("other"),
+            };
+
+            let same = Payload {
+                claims: claim(encoded[name].clone()),
+                ..registered()
+            };
+            assert!(same.to_base64().is_ok(), "repeating {}", name);
+
+            let different = Payload {
+                claims: claim(other.clone()),
+                ..registered()
+            };
+            assert_eq!(
+                Some(&Error::Collision {
+                    what: "claim",
+                    name: name.into(),
+                }),
+                different.to_base64().unwrap_err().downcast_ref::<Error>(),
+                "changing {}",
+                name
+            );
+
+            let unset = Payload {
+                claims: claim(other.clone()),
+                ..Default::default()
+            };
+            let filled: Value = serde_json::to_value(
+                DefaultPayload::from_base64(&unset.to_base64().unwrap()).unwrap(),
+            )
+            .unwrap();
+            assert_eq!(other, filled[name], "filling in {}", name);
+        }
+    }
+
+    #[test]
+    fn float_dates_repeated() {
+        for json in &[
+            r#"{"exp":1700000000.0}"#,
+            r#"{"exp":1.7e9}"#,
+            r#"{"exp":1700000000.25}"#,
+        ] {
+            let payload =
+                Payload::<Value>::from_base64(&encode_config(json, URL_SAFE_NO_PAD)).unwrap();
+            let encoded = payload.to_base64().unwrap();
+            let same = Payload::<Value>::from_base64(&encoded).unwrap();
+
+            assert_eq!(payload.exp, same.exp, "{}", json);
+        }
+    }
+
+    #[test]
+    fn scopes() {
+        let scopes = Scopes::parse("read  write read admin:users");
+
+        assert_eq!(
+            vec!["read", "write", "admin:users"],
+            scopes.iter().collect::<Vec<_>>()
+        );
+        assert!(scopes.has_scope("admin:users"));
+        assert!(!scopes.has_scope("admin"));
+        assert!(scopes.has_all(vec!["read", "write"]));
+        assert!(!scopes.has_all(vec!["read", "delete"]));
+        assert!(scopes.has_any(vec!["delete", "write"]));
+        assert!(!scopes.has_any(vec!["delete"]));
+        assert!(Scopes::parse("").is_empty());
+        assert_eq!(
+            Value::from("read write admin:users"),
+            serde_json::to_value(&scopes).unwrap()
+        );
+    }
+
+    #[test]
+    fn payload_scopes() {
+        let with = |claims: Value| Payload {
+            claims: Some(claims),
+            ..Default::default()
+        };
+        let read = |payload: Payload<Value>| {
+            let scopes = payload.scopes().unwrap();
+            scopes.iter().map(String::from).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec!["read", "write"],
+            read(with(json!({ "scope": "read write" })))
+        );
+        assert_eq!(
+            vec!["read", "write"],
+            read(with(json!({ "scp": ["read", "write"] })))
+        );
+        assert_eq!(
+            vec!["read", "write", "admin"],
+            read(with(
+                json!({ "scope": "read write", "scp": ["write", "admin"] })
+            ))
+        );
+        assert!(read(with(json!({}))).is_empty());
+        assert_eq!(
+            Some(&Error::ClaimType {
+                name: "scp".into(),
+                expected: "a string or an array of strings",
+                found: "a number",
+            }),
+            with(json!({ "scp": 1 }))
+                .scopes()
+                .unwrap_err()
+                .downcast_ref::<Error>()
+        );
+    }
+
+    #[test]
+    fn scopes_in_custom_claims() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Claims {
+            scp: Scopes,
+        }
+        // {"scp":["read","write"]}
+        let payload: Payload<Claims> =
+            Payload::from_base64("eyJzY3AiOlsicmVhZCIsIndyaXRlIl19").unwrap();
+        let claims = payload.claims.unwrap();
+
+        assert!(claims.scp.has_all(vec!["read", "write"]));
+    }
+
+    #[test]
+    fn custom_not_an_object() {
+        let payload = Payload {
+            claims: Some(vec!["admin".to_string()]),
+            ..Default::default()
+        };
+        let err = payload.to_base64().unwrap_err();
+
+        assert_eq!(
+            Some(&Error::NotAnObject {
+                what: "custom claims",
+                found: "an array",
+            }),
+            err.downcast_ref::<Error>()
+        );
+        assert_eq!(
+            "The custom claims must serialize to a JSON object, not an array.",
+            err.to_string()
+        );
     }
 
     fn create_with_nbf(offset: i64) -> DefaultPayload {
         let nbf = (Utc::now() - Duration::minutes(offset)).timestamp();
         DefaultPayload {
-            nbf: Some(nbf as u64),
+            nbf: Some(date(nbf)),
             ..Default::default()
         }
     }
@@ -221,7 +860,7 @@ mod tests {
     fn create_with_exp(offset: i64) -> DefaultPayload {
         let exp = (Utc::now() + Duration::minutes(offset)).timestamp();
         DefaultPayload {
-            exp: Some(exp as u64),
+            exp: Some(date(exp)),
             ..Default::default()
         }
     }
@@ -230,19 +869,23 @@ mod tests {
         let nbf = (Utc::now() - Duration::minutes(nbf_offset)).timestamp();
         let exp = (Utc::now() + Duration::minutes(exp_offset)).timestamp();
         DefaultPayload {
-            nbf: Some(nbf as u64),
-            exp: Some(exp as u64),
+            nbf: Some(date(nbf)),
+            exp: Some(date(exp)),
             ..Default::default()
         }
     }
 
+    fn date(secs: i64) -> NumericDate {
+        NumericDate::from_secs(secs).unwrap()
+    }
+
     fn create_default() -> DefaultPayload {
         DefaultPayload {
             aud: Some("login_service".into()),
-            iat: Some(1_302_317_100),
+            iat: Some(date(1_302_317_100)),
             iss: Some("example.com".into()),
-            exp: Some(1_302_319_100),
-            nbf: Some(1_302_317_100),
+            exp: Some(date(1_302_319_100)),
+            nbf: Some(date(1_302_317_100)),
             sub: Some("Random User".into()),
             ..Default::default()
         }
@@ -251,8 +894,8 @@ mod tests {
     fn create_custom() -> Payload<CustomClaims> {
         Payload {
             iss: Some("example.com".into()),
-            iat: Some(1_302_317_100),
-            exp: Some(1_302_319_100),
+            iat: Some(date(1_302_317_100)),
+            exp: Some(date(1_302_319_100)),
             claims: Some(CustomClaims {
                 user_id: "123456".into(),
                 is_admin: false,
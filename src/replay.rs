@@ -0,0 +1,115 @@
+use super::Result;
+use anyhow::format_err;
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, fmt::Debug, sync::Mutex};
+
+/// Remembers the `jti` of every token verified with it so that a token cannot be used twice. A
+/// cache shared between processes, such as one backed by a database, implements this the same way
+/// as the in-memory one.
+pub trait ReplayCache: Debug + Send + Sync {
+    /// Record the `jti` as seen until the given time, or indefinitely without one, returning
+    /// whether it was new. Once the time has passed at `now` the `jti` may be forgotten, since the
+    /// token will have expired.
+    fn insert(&self, jti: &str, until: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Result<bool>;
+}
+
+/// A replay cache held in memory that remembers at most a given number of `jti`s. Expired ones
+/// are dropped to make room, but a full cache of unexpired ones refuses any more rather than
+/// forgetting a token that could then be replayed.
+#[derive(Debug)]
+pub struct MemoryReplayCache {
+    capacity: usize,
+    seen: Mutex<HashMap<String, Option<DateTime<Utc>>>>,
+}
+
+impl MemoryReplayCache {
+    pub fn new(capacity: usize) -> MemoryReplayCache {
+        MemoryReplayCache {
+            capacity,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The number of `jti`s currently remembered, including any expired ones not yet dropped.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Option<DateTime<Utc>>>> {
+        // each change to the map is a single insert or retain, which a panic cannot leave half done
+        self.seen
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ReplayCache for MemoryReplayCache {
+    fn insert(&self, jti: &str, until: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Result<bool> {
+        let mut seen = self.lock();
+        let live = |until: &Option<DateTime<Utc>>| match *until {
+            Some(until) => now < until,
+            None => true,
+        };
+        if let Some(previous) = seen.get(jti) {
+            if live(previous) {
+                return Ok(false);
+            }
+        } else if seen.len() >= self.capacity {
+            seen.retain(|_, until| live(until));
+            if seen.len() >= self.capacity {
+                return Err(format_err!(
+                    "The replay cache is full with {} unexpired tokens.",
+                    self.capacity
+                ));
+            }
+        }
+        seen.insert(jti.into(), until);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryReplayCache, ReplayCache};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn rejects_replay_until_expiry() {
+        let cache = MemoryReplayCache::new(10);
+        let now = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+        let until = Some(now + Duration::minutes(5));
+
+        assert!(cache.insert("a", until, now).unwrap());
+        assert!(!cache.insert("a", until, now).unwrap());
+        assert!(!cache
+            .insert("a", until, now + Duration::minutes(4))
+            .unwrap());
+        assert!(cache.insert("b", until, now).unwrap());
+        assert!(cache
+            .insert("a", until, now + Duration::minutes(5))
+            .unwrap());
+    }
+
+    #[test]
+    fn bounded() {
+        let cache = MemoryReplayCache::new(2);
+        let now = Utc.timestamp_opt(1_302_317_100, 0).unwrap();
+
+        assert!(cache
+            .insert("a", Some(now + Duration::minutes(1)), now)
+            .unwrap());
+        assert!(cache.insert("b", None, now).unwrap());
+        assert!(cache.insert("c", None, now).is_err());
+        assert_eq!(2, cache.len());
+
+        // the expired one makes room, the one without an expiry is kept
+        let later = now + Duration::minutes(1);
+        assert!(cache.insert("c", None, later).unwrap());
+        assert!(!cache.insert("b", None, later).unwrap());
+        assert_eq!(2, cache.len());
+    }
+}
@@ -0,0 +1,35 @@
+use super::Result;
+use crate::{
+    header::{Algorithm, Header},
+    secret::SecretKey,
+};
+use std::{future::Future, pin::Pin};
+
+/// Looks up the key to verify a token with, given its header, for keys that have to be fetched
+/// from somewhere such as a JWKS endpoint or a key service. The header's `kid` and any custom
+/// headers are available for resolvers that select keys by them.
+pub trait KeyResolver<H> {
+    fn resolve<'a>(
+        &'a self,
+        header: &'a Header<H>,
+    ) -> Pin<Box<dyn Future<Output = Result<ResolvedKey>> + Send + 'a>>;
+}
+
+/// A key found by a `KeyResolver`, along with the one algorithm it may be used with. The header is
+/// written by whoever signed the token, so its `alg` must match rather than being trusted to say
+/// how the key is used.
+#[derive(Debug, Clone)]
+pub struct ResolvedKey {
+    pub algorithm: Algorithm,
+    /// The public key or, for HMAC, the shared secret.
+    pub key: SecretKey,
+}
+
+impl ResolvedKey {
+    pub fn new(algorithm: Algorithm, key: &[u8]) -> ResolvedKey {
+        ResolvedKey {
+            algorithm,
+            key: key.into(),
+        }
+    }
+}
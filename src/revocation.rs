@@ -0,0 +1,193 @@
+use super::Result;
+use crate::date::NumericDate;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// Tokens revoked before they expire, by their `jti` or by every token for a `sub` issued before a
+/// cutoff, as after a logout or a compromised account.
+pub trait RevocationStore: Debug + Send + Sync {
+    /// Whether a token with these claims has been revoked. A token for a revoked `sub` without an
+    /// `iat` is revoked, since it cannot be shown to have been issued after the cutoff.
+    fn is_revoked(
+        &self,
+        jti: Option<&str>,
+        sub: Option<&str>,
+        iat: Option<NumericDate>,
+    ) -> Result<bool>;
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RevocationList {
+    #[serde(default)]
+    jti: HashSet<String>,
+    /// The cutoff for each subject, before which its tokens are revoked.
+    #[serde(default)]
+    sub: HashMap<String, NumericDate>,
+}
+
+impl RevocationList {
+    fn is_revoked(&self, jti: Option<&str>, sub: Option<&str>, iat: Option<NumericDate>) -> bool {
+        let by_jti = match jti {
+            Some(jti) => self.jti.contains(jti),
+            None => false,
+        };
+        let by_sub = match (sub.and_then(|sub| self.sub.get(sub)), iat) {
+            (Some(&issued_before), Some(iat)) => iat < issued_before,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        by_jti || by_sub
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // each change under the lock replaces or inserts whole values, so a panic cannot corrupt one
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A revocation store held in memory and changed through its methods.
+#[derive(Debug, Default)]
+pub struct MemoryRevocationStore {
+    list: Mutex<RevocationList>,
+}
+
+impl MemoryRevocationStore {
+    pub fn new() -> MemoryRevocationStore {
+        MemoryRevocationStore::default()
+    }
+
+    pub fn revoke_jti(&self, jti: &str) {
+        lock(&self.list).jti.insert(jti.into());
+    }
+
+    /// Revoke every token for the subject issued before the cutoff, replacing any earlier cutoff.
+    pub fn revoke_subject(&self, sub: &str, issued_before: NumericDate) {
+        lock(&self.list).sub.insert(sub.into(), issued_before);
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    fn is_revoked(
+        &self,
+        jti: Option<&str>,
+        sub: Option<&str>,
+        iat: Option<NumericDate>,
+    ) -> Result<bool> {
+        Ok(lock(&self.list).is_revoked(jti, sub, iat))
+    }
+}
+
+/// A revocation store read from a JSON file listing revoked `jti`s and a cutoff for each revoked
+/// `sub`, such as `{"jti": ["a1b2"], "sub": {"alice": 1302317100}}`. The file is read again when
+/// its modification time or size changes, and a file that cannot be read is an error rather than
+/// an empty list.
+#[derive(Debug)]
+pub struct FileRevocationStore {
+    path: PathBuf,
+    loaded: Mutex<Loaded>,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    version: (SystemTime, u64),
+    list: RevocationList,
+}
+
+impl FileRevocationStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileRevocationStore> {
+        let path = path.as_ref().to_path_buf();
+        let loaded = Loaded {
+            version: FileRevocationStore::version(&path)?,
+            list: serde_json::from_slice(&fs::read(&path)?)?,
+        };
+        Ok(FileRevocationStore {
+            path,
+            loaded: Mutex::new(loaded),
+        })
+    }
+
+    fn version(path: &Path) -> Result<(SystemTime, u64)> {
+        let metadata = fs::metadata(path)?;
+        Ok((metadata.modified()?, metadata.len()))
+    }
+}
+
+impl RevocationStore for FileRevocationStore {
+    fn is_revoked(
+        &self,
+        jti: Option<&str>,
+        sub: Option<&str>,
+        iat: Option<NumericDate>,
+    ) -> Result<bool> {
+        let mut loaded = lock(&self.loaded);
+        let version = FileRevocationStore::version(&self.path)?;
+        if version != loaded.version {
+            loaded.list = serde_json::from_slice(&fs::read(&self.path)?)?;
+            loaded.version = version;
+        }
+        Ok(loaded.list.is_revoked(jti, sub, iat))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileRevocationStore, MemoryRevocationStore, RevocationStore};
+    use crate::date::NumericDate;
+    use std::{env, fs, process};
+
+    fn date(secs: i64) -> Option<NumericDate> {
+        NumericDate::from_secs(secs).ok()
+    }
+
+    #[test]
+    fn memory() {
+        let store = MemoryRevocationStore::new();
+        assert!(!store.is_revoked(Some("a"), Some("alice"), None).unwrap());
+
+        store.revoke_jti("a");
+        store.revoke_subject("alice", date(1_302_317_100).unwrap());
+
+        assert!(store.is_revoked(Some("a"), None, None).unwrap());
+        assert!(!store.is_revoked(Some("b"), None, None).unwrap());
+        assert!(store
+            .is_revoked(Some("b"), Some("alice"), date(1_302_317_099))
+            .unwrap());
+        assert!(store.is_revoked(None, Some("alice"), None).unwrap());
+        assert!(!store
+            .is_revoked(None, Some("alice"), date(1_302_317_100))
+            .unwrap());
+        assert!(!store.is_revoked(None, Some("bob"), date(0)).unwrap());
+    }
+
+    #[test]
+    fn file_reloads() {
+        let path = env::temp_dir().join(format!("medallion-revoked-{}.json", process::id()));
+        fs::write(&path, r#"{"jti": ["a"]}"#).unwrap();
+        let store = FileRevocationStore::open(&path).unwrap();
+
+        assert!(store.is_revoked(Some("a"), None, None).unwrap());
+        assert!(!store.is_revoked(Some("b"), Some("alice"), date(0)).unwrap());
+
+        fs::write(&path, r#"{"jti": ["b"], "sub": {"alice": 1302317100}}"#).unwrap();
+        assert!(!store.is_revoked(Some("a"), None, None).unwrap());
+        assert!(store.is_revoked(Some("b"), None, None).unwrap());
+        assert!(store.is_revoked(None, Some("alice"), date(0)).unwrap());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(store.is_revoked(Some("b"), None, None).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert!(store.is_revoked(Some("b"), None, None).is_err());
+        assert!(FileRevocationStore::open(&path).is_err());
+    }
+}
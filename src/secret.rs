@@ -0,0 +1,60 @@
+use std::{fmt, ops::Deref};
+use zeroize::Zeroizing;
+
+/// An HMAC secret or private key held by the crate. The bytes are overwritten with zeros when it
+/// is dropped and `Debug` prints `[REDACTED]` rather than the key, so it is safe to keep in structs
+/// that get logged. It dereferences to `&[u8]`, so it can be passed wherever a key is accepted.
+#[derive(Clone, Default)]
+pub struct SecretKey(Zeroizing<Vec<u8>>);
+
+impl SecretKey {
+    pub fn new(key: Vec<u8>) -> SecretKey {
+        SecretKey(Zeroizing::new(key))
+    }
+}
+
+impl From<Vec<u8>> for SecretKey {
+    fn from(key: Vec<u8>) -> SecretKey {
+        SecretKey::new(key)
+    }
+}
+
+impl From<&[u8]> for SecretKey {
+    fn from(key: &[u8]) -> SecretKey {
+        SecretKey::new(key.to_vec())
+    }
+}
+
+impl Deref for SecretKey {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for SecretKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SecretKey;
+
+    #[test]
+    fn redacted_debug() {
+        let key = SecretKey::from(&b"a 32 byte secret key for HS256!!"[..]);
+
+        assert_eq!("[REDACTED]", format!("{:?}", key));
+        assert_eq!("Some([REDACTED])", format!("{:?}", Some(key.clone())));
+        assert_eq!(b"a 32 byte secret key for HS256!!", &*key);
+    }
+}
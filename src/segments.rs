@@ -0,0 +1,76 @@
+use super::Result;
+use crate::token_ref::TokenRef;
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+
+/// The exact form a token was signed in, the compact serialization along with the JSON its header
+/// and payload segments decode to, so that verification checks the very bytes that were signed.
+#[derive(Clone)]
+pub(crate) struct Segments {
+    raw: String,
+    // the lengths of the header segment and of the signing input, each ending just before a period
+    header_len: usize,
+    signing_len: usize,
+    header: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl Segments {
+    /// Split a token into its segments, decoding the header and payload.
+    pub(crate) fn parse(raw: &str) -> Result<Segments> {
+        let parts = TokenRef::parse(raw)?;
+        Ok(Segments {
+            raw: raw.to_owned(),
+            header_len: parts.header_segment().len(),
+            signing_len: parts.signing_input().len(),
+            header: decode_config(parts.header_segment(), URL_SAFE_NO_PAD)?,
+            payload: decode_config(parts.payload_segment(), URL_SAFE_NO_PAD)?,
+        })
+    }
+
+    /// Encode the header and payload JSON as they are about to be signed, taking the signature
+    /// over the signing input from `sign`.
+    pub(crate) fn sign<F>(header: String, payload: String, sign: F) -> Result<Segments>
+    where
+        F: FnOnce(&str) -> Result<String>,
+    {
+        let header_segment = encode_config(header.as_bytes(), URL_SAFE_NO_PAD);
+        let payload_segment = encode_config(payload.as_bytes(), URL_SAFE_NO_PAD);
+        let data = format!("{}.{}", header_segment, payload_segment);
+        let sig = sign(&data)?;
+        Ok(Segments {
+            raw: format!("{}.{}", data, sig),
+            header_len: header_segment.len(),
+            signing_len: data.len(),
+            header: header.into_bytes(),
+            payload: payload.into_bytes(),
+        })
+    }
+
+    pub(crate) fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub(crate) fn header_segment(&self) -> &str {
+        &self.raw[..self.header_len]
+    }
+
+    pub(crate) fn payload_segment(&self) -> &str {
+        &self.raw[self.header_len + 1..self.signing_len]
+    }
+
+    pub(crate) fn signature_segment(&self) -> &str {
+        &self.raw[self.signing_len + 1..]
+    }
+
+    pub(crate) fn signing_input(&self) -> &str {
+        &self.raw[..self.signing_len]
+    }
+
+    pub(crate) fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    pub(crate) fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
@@ -0,0 +1,117 @@
+use super::Result;
+use crate::{
+    crypt::{self, Backend, DefaultBackend},
+    header::Algorithm,
+    secret::SecretKey,
+};
+#[cfg(feature = "async")]
+use std::{future::Future, pin::Pin};
+
+/// Produces the signature over a token's signing input, the base64 encoded header and payload
+/// joined by a period, so that private keys may stay behind a key service or hardware module
+/// rather than being handed to `Token::sign` as bytes.
+pub trait Signer {
+    /// The algorithm the signatures are made with, which must match the token's header.
+    fn algorithm(&self) -> Algorithm;
+
+    /// Sign the data, returning the raw signature bytes. Signatures for the elliptic curve
+    /// algorithms must be the fixed width `r || s` form that JWS uses, not DER.
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// The asynchronous counterpart of `Signer` for key services that are reached over the network or
+/// a socket. Every `Signer` is also an `AsyncSigner` that completes immediately.
+#[cfg(feature = "async")]
+pub trait AsyncSigner {
+    /// The algorithm the signatures are made with, which must match the token's header.
+    fn algorithm(&self) -> Algorithm;
+
+    /// Sign the data, returning the raw signature bytes in the same form as `Signer::sign`.
+    fn sign<'a>(
+        &'a self,
+        data: &'a [u8],
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>>;
+}
+
+#[cfg(feature = "async")]
+impl<S: Signer + Sync> AsyncSigner for S {
+    fn algorithm(&self) -> Algorithm {
+        Signer::algorithm(self)
+    }
+
+    fn sign<'a>(
+        &'a self,
+        data: &'a [u8],
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(std::future::ready(Signer::sign(self, data)))
+    }
+}
+
+/// A `Signer` that holds the key in process and signs with the crate's own cryptographic backend,
+/// accepting the same keys as `Token::sign`.
+#[derive(Debug)]
+pub struct LocalSigner {
+    algorithm: Algorithm,
+    key: SecretKey,
+    allow_weak_keys: bool,
+}
+
+impl LocalSigner {
+    pub fn new(algorithm: Algorithm, key: &[u8]) -> LocalSigner {
+        LocalSigner {
+            algorithm,
+            key: key.into(),
+            allow_weak_keys: false,
+        }
+    }
+
+    /// Sign with a key below the RFC 7518 minimums, only for interoperating with legacy systems.
+    pub fn allow_weak_keys(self) -> LocalSigner {
+        LocalSigner {
+            allow_weak_keys: true,
+            ..self
+        }
+    }
+}
+
+impl Signer for LocalSigner {
+    fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if !self.allow_weak_keys {
+            crypt::check_key_strength(&self.key, &self.algorithm)?;
+        }
+        DefaultBackend::sign(data, &self.key, &self.algorithm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LocalSigner, Signer};
+    use crate::header::Algorithm;
+    use base64::{encode_config, URL_SAFE_NO_PAD};
+
+    #[test]
+    fn local_signer_matches_backend() {
+        let data = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                    eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiYWRtaW4iOnRydWV9";
+        let signer = LocalSigner::new(Algorithm::HS256, b"secret").allow_weak_keys();
+
+        let sig = signer.sign(data.as_bytes()).unwrap();
+
+        assert_eq!(
+            "TJVA95OrM7E2cBab30RMHrHDcEfxjoYZgeFONFh7HgQ",
+            encode_config(&sig, URL_SAFE_NO_PAD)
+        );
+        assert_eq!(Algorithm::HS256, signer.algorithm());
+    }
+
+    #[test]
+    fn local_signer_rejects_weak_key() {
+        let signer = LocalSigner::new(Algorithm::HS256, b"secret");
+
+        assert!(signer.sign(b"data").is_err());
+    }
+}
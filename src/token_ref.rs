@@ -0,0 +1,364 @@
+use super::Result;
+use crate::{
+    error::Error, header::REGISTERED_HEADERS, payload::REGISTERED_CLAIMS, verified::Verified,
+    ClaimsValidator, Header, Payload, Token, VerifyOptions,
+};
+use anyhow::format_err;
+use base64::{decode_config_buf, URL_SAFE_NO_PAD};
+use serde::{
+    de::{value, IntoDeserializer},
+    Deserialize,
+};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// A token parsed without copying, keeping slices of its segments in the string it was parsed
+/// from. Its header and claims are decoded into `DecodeBuffers` that can be reused from one token
+/// to the next, and may borrow strings from them.
+///
+/// Unlike `UnverifiedToken::parse`, custom headers or claims that are present but cannot be read
+/// as their type, such as a string with escapes where a borrowed `&str` is expected, are an error
+/// rather than `None`. They are `None` only when there are no members beyond the registered ones,
+/// or when the type asks for nothing, as `()` does.
+#[derive(Clone, Copy)]
+pub struct TokenRef<'a> {
+    signing_input: &'a str,
+    header: &'a str,
+    payload: &'a str,
+    signature: &'a str,
+}
+
+/// The buffers the segments of a `TokenRef` are decoded into, kept between tokens so that their
+/// allocations are reused.
+#[derive(Debug, Default)]
+pub struct DecodeBuffers {
+    header: Vec<u8>,
+    payload: Vec<u8>,
+}
+
+impl DecodeBuffers {
+    pub fn new() -> DecodeBuffers {
+        DecodeBuffers::default()
+    }
+}
+
+impl<'a> TokenRef<'a> {
+    /// Split a token into its three segments, without decoding them.
+    pub fn parse(raw: &'a str) -> Result<TokenRef<'a>> {
+        let (signing_input, signature) = raw
+            .rsplit_once('.')
+            .ok_or_else(|| format_err!("A token must have exactly three segments."))?;
+        match signing_input.split_once('.') {
+            Some((header, payload)) if !payload.contains('.') => Ok(TokenRef {
+                signing_input,
+                header,
+                payload,
+                signature,
+            }),
+            _ => Err(format_err!("A token must have exactly three segments.")),
+        }
+    }
+
+    /// The base64 encoded header as it was signed.
+    pub fn header_segment(&self) -> &'a str {
+        self.header
+    }
+
+    /// The base64 encoded payload as it was signed.
+    pub fn payload_segment(&self) -> &'a str {
+        self.payload
+    }
+
+    /// The base64 encoded signature.
+    pub fn signature_segment(&self) -> &'a str {
+        self.signature
+    }
+
+    /// The header and payload segments joined by a period, which is what the signature is over.
+    pub fn signing_input(&self) -> &'a str {
+        self.signing_input
+    }
+
+    /// Decode the header and claims without verifying them, which anyone could have written.
+    pub fn insecure_decode<'b, H, C>(&self, buffers: &'b mut DecodeBuffers) -> Result<Token<H, C>>
+    where
+        H: Deserialize<'b>,
+        C: Deserialize<'b>,
+    {
+        buffers.header.clear();
+        decode_config_buf(self.header, URL_SAFE_NO_PAD, &mut buffers.header)?;
+        buffers.payload.clear();
+        decode_config_buf(self.payload, URL_SAFE_NO_PAD, &mut buffers.payload)?;
+
+        let buffers: &'b DecodeBuffers = buffers;
+        let header: Header<H> = serde_json::from_slice(&buffers.header)?;
+        let payload: Payload<C> = serde_json::from_slice(&buffers.payload)?;
+        Ok(Token {
+            segments: None,
+            header: Header {
+                headers: custom(&buffers.header, &REGISTERED_HEADERS)?,
+                ..header
+            },
+            payload: Payload {
+                claims: custom(&buffers.payload, &REGISTERED_CLAIMS)?,
+                unknown: None,
+                ..payload
+            },
+            redact_raw: false,
+        })
+    }
+
+    /// Decode and verify as `Token::verify` does, returning the token as verified when it passes
+    /// and `Error::VerificationFailed` when it does not.
+    pub fn verify<'b, H, C>(
+        &self,
+        key: &[u8],
+        buffers: &'b mut DecodeBuffers,
+    ) -> Result<Verified<Token<H, C>>>
+    where
+        H: Deserialize<'b>,
+        C: Deserialize<'b>,
+    {
+        self.verify_with_options(key, &VerifyOptions::default(), buffers)
+    }
+
+    pub fn verify_with_options<'b, H, C>(
+        &self,
+        key: &[u8],
+        options: &VerifyOptions,
+        buffers: &'b mut DecodeBuffers,
+    ) -> Result<Verified<Token<H, C>>>
+    where
+        H: Deserialize<'b>,
+        C: Deserialize<'b>,
+    {
+        self.verify_with_validator(key, options, &|_: &Payload<C>| Ok(()), buffers)
+    }
+
+    /// Decode and verify as `Token::verify_with_validator` does, failing with the validator's own
+    /// error when it rejects the claims.
+    pub fn verify_with_validator<'b, H, C, V>(
+        &self,
+        key: &[u8],
+        options: &VerifyOptions,
+        validator: &V,
+        buffers: &'b mut DecodeBuffers,
+    ) -> Result<Verified<Token<H, C>>>
+    where
+        H: Deserialize<'b>,
+        C: Deserialize<'b>,
+        V: ClaimsValidator<C> + ?Sized,
+    {
+        let token = self.insecure_decode(buffers)?;
+        if token.verify_signed(
+            self.signature,
+            self.signing_input(),
+            key,
+            options,
+            &|payload| validator.validate(payload),
+        )? {
+            Ok(Verified::new(token))
+        } else {
+            Err(Error::VerificationFailed.into())
+        }
+    }
+}
+
+/// Reads the custom members of a header or payload, telling ones that are missing apart from ones
+/// that do not fit the type.
+fn custom<'b, T: Deserialize<'b>>(data: &'b [u8], registered: &[&str]) -> Result<Option<T>> {
+    let err = match serde_json::from_slice(data) {
+        Ok(custom) => return Ok(Some(custom)),
+        Err(err) => err,
+    };
+    let asks_for_nothing =
+        T::deserialize(IntoDeserializer::<value::Error>::into_deserializer(())).is_ok();
+    let members: Map<String, Value> = serde_json::from_slice(data)?;
+    let present = members
+        .keys()
+        .any(|name| !registered.contains(&name.as_str()));
+    if present && !asks_for_nothing {
+        Err(err.into())
+    } else {
+        Ok(None)
+    }
+}
+
+// leaving out the signature keeps a logged token from being usable as a bearer token
+impl fmt::Debug for TokenRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TokenRef")
+            .field("header", &self.header)
+            .field("payload", &self.payload)
+            .field("signature", &"[REDACTED]")
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodeBuffers, TokenRef};
+    use crate::{ClaimRule, Error, Header, Payload, Token, VerifyOptions};
+    use base64::{encode_config, URL_SAFE_NO_PAD};
+    use serde::{Deserialize, Serialize};
+
+    const KEY: &[u8] = b"a 32 byte secret key for HS256!!";
+
+    #[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
+    struct Owned {
+        tenant: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Borrowed<'a> {
+        tenant: &'a str,
+    }
+
+    fn signed(tenant: &str) -> String {
+        let token = Token::new(
+            Header::<()>::default(),
+            Payload {
+                sub: Some("alice".into()),
+                claims: Some(Owned {
+                    tenant: tenant.into(),
+                }),
+                ..Payload::default()
+            },
+        );
+        token.sign(KEY).unwrap()
+    }
+
+    #[test]
+    fn segments() {
+        let raw = signed("acme");
+        let token = TokenRef::parse(&raw).unwrap();
+        let pieces: Vec<_> = raw.split('.').collect();
+
+        assert_eq!(pieces[0], token.header_segment());
+        assert_eq!(pieces[1], token.payload_segment());
+        assert_eq!(pieces[2], token.signature_segment());
+        assert_eq!(&raw[..raw.rfind('.').unwrap()], token.signing_input());
+    }
+
+    #[test]
+    fn wrong_segment_count() {
+        assert!(TokenRef::parse("a.b").is_err());
+        assert!(TokenRef::parse("a.b.c.d").is_err());
+    }
+
+    #[test]
+    fn borrowed_claims() {
+        let raw = signed("acme");
+        let mut buffers = DecodeBuffers::new();
+        let token = TokenRef::parse(&raw)
+            .unwrap()
+            .verify::<(), Borrowed>(KEY, &mut buffers)
+            .unwrap();
+
+        assert_eq!(Some("alice".into()), token.payload.sub);
+        assert_eq!(
+            Some(Borrowed { tenant: "acme" }),
+            token.into_inner().payload.claims
+        );
+    }
+
+    #[test]
+    fn reused_buffers() {
+        let mut buffers = DecodeBuffers::new();
+        for tenant in &["acme", "initech", "globex"] {
+            let raw = signed(tenant);
+            let token: Token<(), Borrowed> = TokenRef::parse(&raw)
+                .unwrap()
+                .insecure_decode(&mut buffers)
+                .unwrap();
+            assert_eq!(*tenant, token.payload.claims.unwrap().tenant);
+        }
+    }
+
+    fn unsigned(header: &str, payload: &str) -> String {
+        let encode = |json: &str| encode_config(json, URL_SAFE_NO_PAD);
+        format!("{}.{}.c2ln", encode(header), encode(payload))
+    }
+
+    #[test]
+    fn absent_custom_members() {
+        let raw = unsigned(r#"{"alg":"HS256","typ":"JWT"}"#, r#"{"sub":"alice"}"#);
+        let mut buffers = DecodeBuffers::new();
+        let token: Token<(), Borrowed> = TokenRef::parse(&raw)
+            .unwrap()
+            .insecure_decode(&mut buffers)
+            .unwrap();
+
+        assert_eq!(None, token.header.headers);
+        assert_eq!(Some("alice".into()), token.payload.sub);
+        assert_eq!(None, token.payload.claims);
+    }
+
+    #[test]
+    fn malformed_custom_claims() {
+        let header = r#"{"alg":"HS256"}"#;
+        let mut buffers = DecodeBuffers::new();
+        for payload in &[r#"{"tenant":5}"#, r#"{"tenant":"ac\"me"}"#] {
+            let raw = unsigned(header, payload);
+            let decoded = TokenRef::parse(&raw)
+                .unwrap()
+                .insecure_decode::<(), Borrowed>(&mut buffers);
+
+            assert!(decoded.is_err(), "{}", payload);
+        }
+    }
+
+    #[test]
+    fn wrong_key() {
+        let raw = signed("acme");
+        let mut buffers = DecodeBuffers::new();
+        let err = TokenRef::parse(&raw)
+            .unwrap()
+            .verify::<(), Borrowed>(b"a different 32 byte secret key!!", &mut buffers)
+            .unwrap_err();
+
+        assert_eq!(
+            Some(&Error::VerificationFailed),
+            err.downcast_ref::<Error>()
+        );
+    }
+
+    #[test]
+    fn validator() {
+        let raw = signed("acme");
+        let mut buffers = DecodeBuffers::new();
+        let options = VerifyOptions::default();
+        let token = TokenRef::parse(&raw).unwrap();
+
+        assert!(token
+            .verify_with_validator::<(), Owned, _>(
+                KEY,
+                &options,
+                &ClaimRule::equals("tenant", "acme"),
+                &mut buffers,
+            )
+            .is_ok());
+        let err = token
+            .verify_with_validator::<(), Owned, _>(
+                KEY,
+                &options,
+                &ClaimRule::equals("tenant", "initech"),
+                &mut buffers,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidClaim { .. })
+        ));
+    }
+
+    #[test]
+    fn redacted_debug() {
+        let raw = signed("acme");
+        let token = TokenRef::parse(&raw).unwrap();
+        let debug = format!("{:?}", token);
+
+        assert!(!debug.contains(token.signature_segment()));
+        assert!(debug.contains("signature: \"[REDACTED]\""));
+    }
+}
@@ -0,0 +1,237 @@
+use super::Result;
+#[cfg(feature = "async")]
+use crate::resolver::KeyResolver;
+#[cfg(feature = "openssl")]
+use crate::x5c::TrustAnchors;
+use crate::{
+    error::Error, verified::Verified, ClaimsValidator, Header, KeyRing, Payload, Token,
+    VerifyOptions,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A token parsed from a string but not yet verified. Its header and claims are only reachable
+/// through methods named to make the lack of trust plain, for logging or for choosing how to
+/// verify it, and it becomes a `Verified<Token>` only once verified.
+#[derive(Debug)]
+pub struct UnverifiedToken<H = (), C = ()> {
+    token: Token<H, C>,
+}
+
+impl<H, C> UnverifiedToken<H, C>
+where
+    H: Serialize + DeserializeOwned,
+    C: Serialize + DeserializeOwned,
+{
+    pub fn parse(raw: &str) -> Result<UnverifiedToken<H, C>> {
+        Ok(UnverifiedToken {
+            token: Token::from_raw(raw)?,
+        })
+    }
+
+    /// The header as sent, which anyone could have written.
+    pub fn insecure_header(&self) -> &Header<H> {
+        &self.token.header
+    }
+
+    /// The claims as sent, which anyone could have written.
+    pub fn insecure_claims(&self) -> &Payload<C> {
+        &self.token.payload
+    }
+
+    /// Verify as `Token::verify` does, consuming the token and returning it as verified when it
+    /// passes and `Error::VerificationFailed` when it does not.
+    pub fn verify(self, key: &[u8]) -> Result<Verified<Token<H, C>>> {
+        self.verify_with_options(key, &VerifyOptions::default())
+    }
+
+    pub fn verify_with_options(
+        self,
+        key: &[u8],
+        options: &VerifyOptions,
+    ) -> Result<Verified<Token<H, C>>> {
+        let verified = self.token.verify_with_options(key, options)?;
+        self.accept(verified)
+    }
+
+    /// Verify as `Token::verify_with_validator` does, failing with the validator's own error when
+    /// it rejects the claims.
+    pub fn verify_with_validator<V>(
+        self,
+        key: &[u8],
+        options: &VerifyOptions,
+        validator: &V,
+    ) -> Result<Verified<Token<H, C>>>
+    where
+        V: ClaimsValidator<C> + ?Sized,
+    {
+        let verified = self.token.verify_with_validator(key, options, validator)?;
+        self.accept(verified)
+    }
+
+    pub fn verify_with_key_ring(
+        self,
+        ring: &KeyRing,
+        options: &VerifyOptions,
+    ) -> Result<Verified<Token<H, C>>> {
+        let verified = ring.verify_with_options(&self.token, options)?;
+        self.accept(verified)
+    }
+
+    /// Verify as `KeyRing::verify_with_validator` does, failing with the validator's own error
+    /// when it rejects the claims.
+    pub fn verify_with_key_ring_and_validator<V>(
+        self,
+        ring: &KeyRing,
+        options: &VerifyOptions,
+        validator: &V,
+    ) -> Result<Verified<Token<H, C>>>
+    where
+        V: ClaimsValidator<C> + ?Sized,
+    {
+        let verified = ring.verify_with_validator(&self.token, options, validator)?;
+        self.accept(verified)
+    }
+
+    #[cfg(feature = "openssl")]
+    pub fn verify_x5c(self, anchors: &TrustAnchors) -> Result<Verified<Token<H, C>>> {
+        let verified = self.token.verify_x5c(anchors)?;
+        self.accept(verified)
+    }
+
+    #[cfg(feature = "openssl")]
+    pub fn verify_x5c_with_options(
+        self,
+        anchors: &TrustAnchors,
+        options: &VerifyOptions,
+    ) -> Result<Verified<Token<H, C>>> {
+        let verified = self.token.verify_x5c_with_options(anchors, options)?;
+        self.accept(verified)
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn verify_with<R: KeyResolver<H> + ?Sized>(
+        self,
+        resolver: &R,
+    ) -> Result<Verified<Token<H, C>>> {
+        let verified = self.token.verify_with(resolver).await?;
+        self.accept(verified)
+    }
+
+    /// Verify as `Token::verify_with_and_validator` does, failing with the validator's own error
+    /// when it rejects the claims.
+    #[cfg(feature = "async")]
+    pub async fn verify_with_and_validator<R, V>(
+        self,
+        resolver: &R,
+        options: &VerifyOptions,
+        validator: &V,
+    ) -> Result<Verified<Token<H, C>>>
+    where
+        R: KeyResolver<H> + ?Sized,
+        V: ClaimsValidator<C> + ?Sized,
+    {
+        let verified = self
+            .token
+            .verify_with_and_validator(resolver, options, validator)
+            .await?;
+        self.accept(verified)
+    }
+
+    fn accept(self, verified: bool) -> Result<Verified<Token<H, C>>> {
+        if verified {
+            Ok(Verified::new(self.token))
+        } else {
+            Err(Error::VerificationFailed.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnverifiedToken;
+    use crate::Verified;
+    use crate::{
+        Algorithm::HS256, ClaimRule, Error, Header, KeyRing, Payload, Token, VerifyOptions,
+    };
+
+    const KEY: &[u8] = b"a 32 byte secret key for HS256!!";
+
+    fn signed() -> String {
+        let token: Token = Token::new(
+            Header {
+                kid: Some("current".into()),
+                ..Header::default()
+            },
+            Payload {
+                iss: Some("example.com".into()),
+                ..Payload::default()
+            },
+        );
+        token.sign(KEY).unwrap()
+    }
+
+    #[test]
+    fn inspect_then_verify() {
+        let unverified: UnverifiedToken = UnverifiedToken::parse(&signed()).unwrap();
+        assert_eq!(Some("current".into()), unverified.insecure_header().kid);
+        assert_eq!(Some("example.com".into()), unverified.insecure_claims().iss);
+
+        let token = unverified.verify(KEY).unwrap();
+        assert_eq!(Some("example.com".into()), token.payload.iss);
+    }
+
+    fn issuer(token: &Verified<Token>) -> Option<&str> {
+        token.payload.iss.as_deref()
+    }
+
+    #[test]
+    fn handler_requires_verified() {
+        let token = UnverifiedToken::parse(&signed())
+            .unwrap()
+            .verify(KEY)
+            .unwrap();
+
+        assert_eq!(Some("example.com"), issuer(&token));
+        assert_eq!(Some("current".into()), token.into_inner().header.kid);
+    }
+
+    #[test]
+    fn wrong_key() {
+        let unverified: UnverifiedToken = UnverifiedToken::parse(&signed()).unwrap();
+        let err = unverified
+            .verify(b"a different 32 byte secret key!!")
+            .unwrap_err();
+
+        assert_eq!(
+            Some(&Error::VerificationFailed),
+            err.downcast_ref::<Error>()
+        );
+    }
+
+    #[test]
+    fn validator() {
+        let rule = ClaimRule::equals("iss", "other.example.com");
+        let err = UnverifiedToken::<(), ()>::parse(&signed())
+            .unwrap()
+            .verify_with_validator(KEY, &VerifyOptions::default(), &rule)
+            .unwrap_err();
+
+        assert_eq!(
+            Some(&Error::InvalidClaim {
+                name: "iss".into(),
+                expected: "equal to \"other.example.com\"".into(),
+            }),
+            err.downcast_ref::<Error>()
+        );
+    }
+
+    #[test]
+    fn key_ring() {
+        let ring = KeyRing::new("current", HS256, KEY, KEY);
+        let unverified: UnverifiedToken = UnverifiedToken::parse(&signed()).unwrap();
+
+        assert!(unverified
+            .verify_with_key_ring(&ring, &VerifyOptions::default())
+            .is_ok());
+    }
+}
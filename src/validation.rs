@@ -0,0 +1,345 @@
+use super::Result;
+use crate::{error::Error, Payload, Scopes};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Checks the claims of a token once its signature and times have been verified, returning an
+/// error to reject it. The error is returned from verification as it is, so a validator may use
+/// its own error types and callers recover them with `downcast_ref`. Closures taking the payload
+/// are validators, as are slices and `Vec`s of validators, which run in order.
+pub trait ClaimsValidator<C> {
+    fn validate(&self, payload: &Payload<C>) -> Result<()>;
+
+    /// Validate as `validate` does, given the claims as a map once some validator in the same list
+    /// has built it, so that a list of rules turns the payload into a map only once.
+    #[doc(hidden)]
+    fn validate_sharing(
+        &self,
+        payload: &Payload<C>,
+        _claims: &mut Option<Map<String, Value>>,
+    ) -> Result<()> {
+        self.validate(payload)
+    }
+}
+
+impl<C, F> ClaimsValidator<C> for F
+where
+    F: Fn(&Payload<C>) -> Result<()>,
+{
+    fn validate(&self, payload: &Payload<C>) -> Result<()> {
+        self(payload)
+    }
+}
+
+impl<C, V: ClaimsValidator<C>> ClaimsValidator<C> for [V] {
+    fn validate(&self, payload: &Payload<C>) -> Result<()> {
+        self.validate_sharing(payload, &mut None)
+    }
+
+    fn validate_sharing(
+        &self,
+        payload: &Payload<C>,
+        claims: &mut Option<Map<String, Value>>,
+    ) -> Result<()> {
+        self.iter()
+            .try_for_each(|validator| validator.validate_sharing(payload, claims))
+    }
+}
+
+// boxing lets rules and closures be mixed in one list
+impl<C> ClaimsValidator<C> for Box<dyn ClaimsValidator<C> + '_> {
+    fn validate(&self, payload: &Payload<C>) -> Result<()> {
+        (**self).validate(payload)
+    }
+
+    fn validate_sharing(
+        &self,
+        payload: &Payload<C>,
+        claims: &mut Option<Map<String, Value>>,
+    ) -> Result<()> {
+        (**self).validate_sharing(payload, claims)
+    }
+}
+
+impl<C, V: ClaimsValidator<C>> ClaimsValidator<C> for Vec<V> {
+    fn validate(&self, payload: &Payload<C>) -> Result<()> {
+        self.as_slice().validate(payload)
+    }
+
+    fn validate_sharing(
+        &self,
+        payload: &Payload<C>,
+        claims: &mut Option<Map<String, Value>>,
+    ) -> Result<()> {
+        self.as_slice().validate_sharing(payload, claims)
+    }
+}
+
+/// A rule for a single claim, registered or custom, found by its name in the payload as it would
+/// be encoded. A missing claim never passes, and a failing one is an `Error::InvalidClaim`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimRule {
+    name: String,
+    check: Check,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Check {
+    Equals(Value),
+    OneOf(Vec<Value>),
+    ContainsAll(Vec<Value>),
+    Scopes(Scopes),
+}
+
+impl ClaimRule {
+    /// The claim must have exactly this value.
+    pub fn equals<V: Into<Value>>(name: &str, value: V) -> ClaimRule {
+        ClaimRule::new(name, Check::Equals(value.into()))
+    }
+
+    /// The claim must have one of these values.
+    pub fn one_of<V, I>(name: &str, values: I) -> ClaimRule
+    where
+        V: Into<Value>,
+        I: IntoIterator<Item = V>,
+    {
+        ClaimRule::new(
+            name,
+            Check::OneOf(values.into_iter().map(Into::into).collect()),
+        )
+    }
+
+    /// The claim must be an array holding every one of these values, and possibly others.
+    pub fn contains_all<V, I>(name: &str, values: I) -> ClaimRule
+    where
+        V: Into<Value>,
+        I: IntoIterator<Item = V>,
+    {
+        ClaimRule::new(
+            name,
+            Check::ContainsAll(values.into_iter().map(Into::into).collect()),
+        )
+    }
+
+    /// The token's scopes, read from `scope` or `scp` as `Payload::scopes` does, must include every
+    /// one of these.
+    pub fn requires_scopes<'a, I: IntoIterator<Item = &'a str>>(scopes: I) -> ClaimRule {
+        ClaimRule::new("scope", Check::Scopes(scopes.into_iter().collect()))
+    }
+
+    fn new(name: &str, check: Check) -> ClaimRule {
+        ClaimRule {
+            name: name.into(),
+            check,
+        }
+    }
+
+    fn passes(&self, claims: &Map<String, Value>) -> Result<bool> {
+        let claim = claims.get(&self.name);
+        Ok(match self.check {
+            Check::Equals(ref expected) => claim == Some(expected),
+            Check::OneOf(ref allowed) => match claim {
+                Some(value) => allowed.contains(value),
+                None => false,
+            },
+            Check::ContainsAll(ref required) => match claim {
+                Some(Value::Array(held)) => required.iter().all(|value| held.contains(value)),
+                _ => false,
+            },
+            Check::Scopes(ref required) => Scopes::from_claims(claims)?.has_all(required.iter()),
+        })
+    }
+
+    fn expected(&self) -> String {
+        match self.check {
+            Check::Equals(ref value) => format!("equal to {}", value),
+            Check::OneOf(ref values) => format!("one of {}", Value::from(values.clone())),
+            Check::ContainsAll(ref values) => {
+                format!("an array with all of {}", Value::from(values.clone()))
+            }
+            Check::Scopes(ref scopes) => {
+                format!(
+                    "scopes including {}",
+                    Value::from(scopes.iter().collect::<Vec<_>>())
+                )
+            }
+        }
+    }
+}
+
+impl<C: Serialize> ClaimsValidator<C> for ClaimRule {
+    fn validate(&self, payload: &Payload<C>) -> Result<()> {
+        self.validate_sharing(payload, &mut None)
+    }
+
+    fn validate_sharing(
+        &self,
+        payload: &Payload<C>,
+        claims: &mut Option<Map<String, Value>>,
+    ) -> Result<()> {
+        let claims = match *claims {
+            Some(ref claims) => claims,
+            None => claims.get_or_insert(payload.to_map()?),
+        };
+        if self.passes(claims)? {
+            Ok(())
+        } else {
+            Err(Error::InvalidClaim {
+                name: self.name.clone(),
+                expected: self.expected(),
+            }
+            .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClaimRule, ClaimsValidator};
+    use crate::{Error, Payload};
+    use serde::{Deserialize, Serialize};
+    use serde_json::{json, Value};
+
+    #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+    struct Claims {
+        tenant_id: String,
+        groups: Vec<String>,
+    }
+
+    fn payload() -> Payload<Claims> {
+        Payload {
+            iss: Some("example.com".into()),
+            claims: Some(Claims {
+                tenant_id: "acme".into(),
+                groups: vec!["staff".into(), "billing".into()],
+            }),
+            ..Payload::default()
+        }
+    }
+
+    fn invalid(name: &str, expected: &str) -> Option<Error> {
+        Some(Error::InvalidClaim {
+            name: name.into(),
+            expected: expected.into(),
+        })
+    }
+
+    fn check<V: ClaimsValidator<Claims> + ?Sized>(validator: &V) -> Option<Error> {
+        validator
+            .validate(&payload())
+            .err()
+            .map(|err| err.downcast::<Error>().unwrap())
+    }
+
+    #[test]
+    fn equals() {
+        assert_eq!(None, check(&ClaimRule::equals("tenant_id", "acme")));
+        assert_eq!(None, check(&ClaimRule::equals("iss", "example.com")));
+        assert_eq!(
+            invalid("tenant_id", "equal to \"initech\""),
+            check(&ClaimRule::equals("tenant_id", "initech"))
+        );
+        assert_eq!(
+            invalid("sub", "equal to \"alice\""),
+            check(&ClaimRule::equals("sub", "alice"))
+        );
+    }
+
+    #[test]
+    fn one_of() {
+        assert_eq!(
+            None,
+            check(&ClaimRule::one_of("tenant_id", vec!["acme", "initech"]))
+        );
+        assert_eq!(
+            invalid("tenant_id", "one of [\"globex\",\"initech\"]"),
+            check(&ClaimRule::one_of("tenant_id", vec!["globex", "initech"]))
+        );
+    }
+
+    #[test]
+    fn contains_all() {
+        assert_eq!(
+            None,
+            check(&ClaimRule::contains_all("groups", vec!["billing", "staff"]))
+        );
+        assert_eq!(
+            invalid("groups", "an array with all of [\"staff\",\"admin\"]"),
+            check(&ClaimRule::contains_all("groups", vec!["staff", "admin"]))
+        );
+        assert_eq!(
+            invalid("tenant_id", "an array with all of [\"acme\"]"),
+            check(&ClaimRule::contains_all("tenant_id", vec!["acme"]))
+        );
+    }
+
+    #[test]
+    fn requires_scopes() {
+        let scoped = |scope: &str| Payload {
+            claims: Some(json!({ "scope": scope })),
+            ..Payload::default()
+        };
+        let rule = ClaimRule::requires_scopes(vec!["read", "write"]);
+
+        assert!(rule.validate(&scoped("read write admin")).is_ok());
+        assert_eq!(
+            Some(&Error::InvalidClaim {
+                name: "scope".into(),
+                expected: "scopes including [\"read\",\"write\"]".into(),
+            }),
+            rule.validate(&scoped("read"))
+                .unwrap_err()
+                .downcast_ref::<Error>()
+        );
+        assert!(rule.validate(&Payload::<Value>::default()).is_err());
+    }
+
+    #[derive(Debug)]
+    struct WrongTenant(String);
+
+    impl std::fmt::Display for WrongTenant {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "Tenant {} is not served here.", self.0)
+        }
+    }
+
+    impl std::error::Error for WrongTenant {}
+
+    #[test]
+    fn closures_and_lists() {
+        let tenant = |payload: &Payload<Claims>| -> crate::Result<()> {
+            match payload.claims {
+                Some(ref claims) if claims.tenant_id == "initech" => Ok(()),
+                Some(ref claims) => Err(WrongTenant(claims.tenant_id.clone()).into()),
+                None => Err(WrongTenant(String::new()).into()),
+            }
+        };
+        let err = tenant.validate(&payload()).unwrap_err();
+        assert_eq!("acme", err.downcast_ref::<WrongTenant>().unwrap().0);
+
+        let rules = vec![
+            ClaimRule::equals("iss", "example.com"),
+            ClaimRule::contains_all("groups", vec!["admin"]),
+            ClaimRule::equals("tenant_id", "initech"),
+        ];
+        assert_eq!(
+            invalid("groups", "an array with all of [\"admin\"]"),
+            check(&rules)
+        );
+        assert_eq!(None, check(&rules[..1]));
+
+        let mut claims = None;
+        assert!(rules[..1].validate_sharing(&payload(), &mut claims).is_ok());
+        assert_eq!(Some(&json!("acme")), claims.unwrap().get("tenant_id"));
+
+        let mixed: Vec<Box<dyn ClaimsValidator<Claims>>> = vec![
+            Box::new(ClaimRule::equals("iss", "example.com")),
+            Box::new(tenant),
+        ];
+        assert!(mixed
+            .validate(&payload())
+            .unwrap_err()
+            .downcast_ref::<WrongTenant>()
+            .is_some());
+    }
+}
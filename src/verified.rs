@@ -0,0 +1,26 @@
+use std::ops::Deref;
+
+/// Proof that the value inside has passed verification, which only this crate can give. A function
+/// taking a `Verified<Token<H, C>>` cannot be handed a token that was merely parsed. It derefs to
+/// the value but does not allow changing it.
+#[derive(Debug)]
+pub struct Verified<T>(T);
+
+impl<T> Verified<T> {
+    pub(crate) fn new(value: T) -> Verified<T> {
+        Verified(value)
+    }
+
+    /// Give up the proof to take the value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Verified<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
@@ -0,0 +1,399 @@
+use super::Result;
+use crate::header::{Algorithm, Header};
+use anyhow::format_err;
+use base64::{decode, encode_config, URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use openssl::{
+    hash::MessageDigest,
+    memcmp,
+    nid::Nid,
+    pkey::{Id, PKeyRef, Public},
+    stack::Stack,
+    x509::{
+        store::{X509Store, X509StoreBuilder},
+        verify::X509VerifyParam,
+        X509StoreContext, X509,
+    },
+};
+use std::convert::TryFrom;
+
+/// The root certificates a chain from a token's `x5c` header must lead back to.
+pub struct TrustAnchors {
+    certs: Vec<X509>,
+}
+
+impl TrustAnchors {
+    /// Trust every certificate in a PEM bundle.
+    pub fn from_pem(pem: &[u8]) -> Result<TrustAnchors> {
+        let certs = X509::stack_from_pem(pem)?;
+        if certs.is_empty() {
+            return Err(format_err!("No certificates found to trust."));
+        }
+
+        Ok(TrustAnchors { certs })
+    }
+
+    /// A store of the anchors that checks certificates are valid at the given time rather than at
+    /// the system's.
+    fn store(&self, at: DateTime<Utc>) -> Result<X509Store> {
+        let mut param = X509VerifyParam::new()?;
+        // time_t is narrower than an i64 on some targets
+        #[allow(clippy::useless_conversion)]
+        param.set_time(TryFrom::try_from(at.timestamp())?);
+
+        let mut builder = X509StoreBuilder::new()?;
+        for cert in &self.certs {
+            builder.add_cert(cert.clone())?;
+        }
+        builder.set_param(&param)?;
+        Ok(builder.build())
+    }
+}
+
+/// Validates the chain in the header's `x5c` against the trust anchors at the given time and checks
+/// any `x5t` or `x5t#S256` thumbprints against the leaf, returning the leaf's public key as PEM.
+/// The header's `alg` must be an asymmetric algorithm for the type of the leaf's key.
+pub fn verification_key<H>(
+    header: &Header<H>,
+    anchors: &TrustAnchors,
+    at: DateTime<Utc>,
+) -> Result<Vec<u8>> {
+    let mut certs = match header.x5c {
+        Some(ref x5c) if !x5c.is_empty() => x5c
+            .iter()
+            // unlike the rest of a JWT, x5c uses standard, padded base64
+            .map(|cert| Ok(X509::from_der(&decode(cert)?)?))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter(),
+        _ => return Err(format_err!("Header has no x5c certificate chain.")),
+    };
+    let leaf = certs.next().expect("checked the chain is not empty");
+    let mut chain = Stack::new()?;
+    for cert in certs {
+        chain.push(cert)?;
+    }
+
+    let store = anchors.store(at)?;
+    let mut context = X509StoreContext::new()?;
+    let (valid, error) = context.init(&store, &leaf, &chain, |context| {
+        Ok((context.verify_cert()?, context.error()))
+    })?;
+    if !valid {
+        return Err(format_err!("Invalid x5c certificate chain: {}", error));
+    }
+
+    check_thumbprint(&leaf, header.x5t.as_deref(), MessageDigest::sha1(), "x5t")?;
+    check_thumbprint(
+        &leaf,
+        header.x5t_s256.as_deref(),
+        MessageDigest::sha256(),
+        "x5t#S256",
+    )?;
+
+    let key = leaf.public_key()?;
+    check_algorithm(&key, header.alg)?;
+    Ok(key.public_key_to_pem()?)
+}
+
+// the header is written by the signer, so without this an HMAC algorithm would have the public
+// key in the certificate used as the secret
+fn check_algorithm(key: &PKeyRef<Public>, alg: Algorithm) -> Result<()> {
+    let curve = || key.ec_key().ok().and_then(|ec| ec.group().curve_name());
+    let matches = match alg {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            return Err(format_err!(
+                "{:?} cannot be verified with an x5c certificate.",
+                alg
+            ))
+        }
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => key.id() == Id::RSA,
+        Algorithm::ES256 => curve() == Some(Nid::X9_62_PRIME256V1),
+        Algorithm::ES384 => curve() == Some(Nid::SECP384R1),
+        Algorithm::ES512 => curve() == Some(Nid::SECP521R1),
+        Algorithm::EdDSA => key.id() == Id::ED25519,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(format_err!(
+            "The leaf certificate's key cannot be used with {:?}.",
+            alg
+        ))
+    }
+}
+
+fn check_thumbprint(
+    leaf: &X509,
+    expected: Option<&str>,
+    digest: MessageDigest,
+    name: &str,
+) -> Result<()> {
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let actual = encode_config(leaf.digest(digest)?, URL_SAFE_NO_PAD);
+    if actual.len() == expected.len() && memcmp::eq(actual.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err(format_err!("{} does not match the leaf certificate.", name))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{verification_key, TrustAnchors};
+    use crate::{Algorithm, DefaultToken, FixedClock, Header, VerifyOptions};
+    use base64::{encode, encode_config, URL_SAFE_NO_PAD};
+    use chrono::{Duration, Utc};
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        hash::MessageDigest,
+        pkey::{PKey, Private},
+        rsa::Rsa,
+        x509::{
+            extension::{BasicConstraints, KeyUsage},
+            X509Name, X509,
+        },
+    };
+    use std::sync::Arc;
+
+    pub struct Issued {
+        pub cert: X509,
+        pub key: PKey<Private>,
+    }
+
+    /// Issues a certificate for a new RSA key, self-signed when there is no issuer.
+    pub fn issue(name: &str, issuer: Option<&Issued>, ca: bool) -> Issued {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut subject = X509Name::builder().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = BigNum::from_u32(rand_serial()).unwrap();
+        builder
+            .set_serial_number(&serial.to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder
+            .set_issuer_name(issuer.map_or(&subject, |i| i.cert.subject_name()))
+            .unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        if ca {
+            builder
+                .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+            builder
+                .append_extension(
+                    KeyUsage::new()
+                        .critical()
+                        .key_cert_sign()
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+        }
+        builder
+            .sign(issuer.map_or(&key, |i| &i.key), MessageDigest::sha256())
+            .unwrap();
+
+        Issued {
+            cert: builder.build(),
+            key,
+        }
+    }
+
+    fn rand_serial() -> u32 {
+        let mut buf = [0; 4];
+        openssl::rand::rand_bytes(&mut buf).unwrap();
+        u32::from_be_bytes(buf) >> 1
+    }
+
+    /// A root, an intermediate issued by it and a leaf issued by the intermediate.
+    fn chain() -> (Issued, Issued, Issued) {
+        let root = issue("Root CA", None, true);
+        let intermediate = issue("Intermediate CA", Some(&root), true);
+        let leaf = issue("Signer", Some(&intermediate), false);
+        (root, intermediate, leaf)
+    }
+
+    fn x5c(certs: &[&X509]) -> Option<Vec<String>> {
+        Some(
+            certs
+                .iter()
+                .map(|cert| encode(cert.to_der().unwrap()))
+                .collect(),
+        )
+    }
+
+    fn anchors(root: &Issued) -> TrustAnchors {
+        TrustAnchors::from_pem(&root.cert.to_pem().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn valid_chain() {
+        let (root, intermediate, leaf) = chain();
+        let header: Header = Header {
+            alg: Algorithm::RS256,
+            x5c: x5c(&[&leaf.cert, &intermediate.cert]),
+            ..Header::default()
+        };
+
+        let key = verification_key(&header, &anchors(&root), Utc::now()).unwrap();
+
+        assert_eq!(leaf.key.public_key_to_pem().unwrap(), key);
+    }
+
+    #[test]
+    fn untrusted_root() {
+        let (_, intermediate, leaf) = chain();
+        let other = issue("Other CA", None, true);
+        let header: Header = Header {
+            x5c: x5c(&[&leaf.cert, &intermediate.cert]),
+            ..Header::default()
+        };
+
+        assert!(verification_key(&header, &anchors(&other), Utc::now()).is_err());
+    }
+
+    #[test]
+    fn missing_intermediate() {
+        let (root, _, leaf) = chain();
+        let header: Header = Header {
+            x5c: x5c(&[&leaf.cert]),
+            ..Header::default()
+        };
+
+        assert!(verification_key(&header, &anchors(&root), Utc::now()).is_err());
+    }
+
+    #[test]
+    fn missing_x5c() {
+        let (root, _, _) = chain();
+        let header: Header = Header::default();
+
+        assert!(verification_key(&header, &anchors(&root), Utc::now()).is_err());
+    }
+
+    #[test]
+    fn thumbprints() {
+        let (root, intermediate, leaf) = chain();
+        let sha1 = leaf.cert.digest(MessageDigest::sha1()).unwrap();
+        let sha256 = leaf.cert.digest(MessageDigest::sha256()).unwrap();
+        let mut header: Header = Header {
+            alg: Algorithm::RS256,
+            x5c: x5c(&[&leaf.cert, &intermediate.cert]),
+            x5t: Some(encode_config(sha1, URL_SAFE_NO_PAD)),
+            x5t_s256: Some(encode_config(sha256, URL_SAFE_NO_PAD)),
+            ..Header::default()
+        };
+        assert!(verification_key(&header, &anchors(&root), Utc::now()).is_ok());
+
+        header.x5t_s256 = Some(encode_config(sha1, URL_SAFE_NO_PAD));
+        assert!(verification_key(&header, &anchors(&root), Utc::now()).is_err());
+
+        header.x5t_s256 = None;
+        header.x5t = Some(encode_config(sha256, URL_SAFE_NO_PAD));
+        assert!(verification_key(&header, &anchors(&root), Utc::now()).is_err());
+    }
+
+    #[test]
+    fn verify_token() {
+        let (root, intermediate, leaf) = chain();
+        let token: DefaultToken<()> = DefaultToken::new(
+            Header {
+                alg: Algorithm::RS256,
+                x5c: x5c(&[&leaf.cert, &intermediate.cert]),
+                ..Header::default()
+            },
+            Default::default(),
+        );
+        let raw = token
+            .sign(&leaf.key.rsa().unwrap().private_key_to_pem().unwrap())
+            .unwrap();
+        let same = DefaultToken::<()>::from_raw(&raw).unwrap();
+
+        assert!(same.verify_x5c(&anchors(&root)).unwrap());
+
+        let impostor = issue("Signer", Some(&intermediate), false);
+        let forged = token
+            .sign(&impostor.key.rsa().unwrap().private_key_to_pem().unwrap())
+            .unwrap();
+        let forged = DefaultToken::<()>::from_raw(&forged).unwrap();
+
+        assert!(!forged.verify_x5c(&anchors(&root)).unwrap());
+    }
+
+    #[test]
+    fn certificates_checked_at_clock_time() {
+        let (root, intermediate, leaf) = chain();
+        let token: DefaultToken<()> = DefaultToken::new(
+            Header {
+                alg: Algorithm::RS256,
+                x5c: x5c(&[&leaf.cert, &intermediate.cert]),
+                ..Header::default()
+            },
+            Default::default(),
+        );
+        let raw = token
+            .sign(&leaf.key.rsa().unwrap().private_key_to_pem().unwrap())
+            .unwrap();
+        let same = DefaultToken::<()>::from_raw(&raw).unwrap();
+        let at = |time| VerifyOptions {
+            clock: Arc::new(FixedClock::new(time)),
+            ..VerifyOptions::default()
+        };
+
+        assert!(same
+            .verify_x5c_with_options(&anchors(&root), &at(Utc::now()))
+            .unwrap());
+        // the certificates are valid for a day from when they were issued
+        let expired = at(Utc::now() + Duration::days(2));
+        assert!(same
+            .verify_x5c_with_options(&anchors(&root), &expired)
+            .is_err());
+        let before = Utc::now() - Duration::days(1);
+        assert!(verification_key(&same.header, &anchors(&root), before).is_err());
+    }
+
+    #[test]
+    fn forged_hmac_token() {
+        let (root, intermediate, leaf) = chain();
+        // the leaf's public key is no secret, so anyone could sign with it as an HMAC key
+        let public = leaf.key.public_key_to_pem().unwrap();
+        let forged: DefaultToken<()> = DefaultToken::new(
+            Header {
+                alg: Algorithm::HS256,
+                x5c: x5c(&[&leaf.cert, &intermediate.cert]),
+                ..Header::default()
+            },
+            Default::default(),
+        );
+        let forged = DefaultToken::<()>::from_raw(&forged.sign(&public).unwrap()).unwrap();
+
+        assert!(forged.verify_x5c(&anchors(&root)).is_err());
+    }
+
+    #[test]
+    fn algorithm_for_another_key_type() {
+        let (root, intermediate, leaf) = chain();
+        for &alg in &[Algorithm::HS512, Algorithm::ES256, Algorithm::EdDSA] {
+            let header: Header = Header {
+                alg,
+                x5c: x5c(&[&leaf.cert, &intermediate.cert]),
+                ..Header::default()
+            };
+
+            assert!(verification_key(&header, &anchors(&root), Utc::now()).is_err());
+        }
+    }
+}